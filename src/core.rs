@@ -1,2 +1,7 @@
 pub mod auth;
+pub mod cache;
+pub mod certificate;
+pub mod checkpoint;
 pub mod constants;
+pub mod output;
+pub mod recon;
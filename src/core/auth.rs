@@ -1,21 +1,36 @@
-use crate::helpers::ClientConfig;
-use chrono::Utc;
+use crate::error::{Result, RevelioError};
+use crate::helpers::{write_private_file, AuthFlow, ClientConfig};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{TimeZone, Utc};
 use colored::Colorize;
-use oauth2::basic::BasicClient;
+use oauth2::basic::{BasicClient, BasicTokenResponse};
 use oauth2::reqwest::async_http_client;
+use oauth2::url::Url;
 use oauth2::{
-    AccessToken, AuthUrl, ClientId, ClientSecret, DeviceAuthorizationUrl, Scope,
+    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken,
+    DeviceAuthorizationUrl, PkceCodeChallenge, RedirectUrl, RefreshToken, Scope,
     StandardDeviceAuthorizationResponse, TokenResponse, TokenUrl,
     ResourceOwnerPassword, ResourceOwnerUsername,
 };
+use crate::core::constants::CLIENT_ASSERTION_TYPE;
 use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::Write;
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthResult {
     pub credential: ClientConfig,
     pub auth_token: Option<String>,
+    pub refresh_token: Option<String>,
+    /// Expiration time of the access token (Unix timestamp)
+    pub expires_at: Option<i64>,
+    /// Scopes granted to the access token, if returned by the server
+    pub scopes: Option<Vec<String>>,
 }
 
 impl AuthResult {
@@ -23,13 +38,16 @@ impl AuthResult {
         AuthResult {
             credential,
             auth_token,
+            refresh_token: None,
+            expires_at: None,
+            scopes: None,
         }
     }
 }
 
-pub async fn authenticate_credential_device(config: &ClientConfig) -> AuthResult {
-    let client = create_oauth_client(config);
-    let auth_url_details = generate_auth_url(&client, config).await;
+pub async fn authenticate_credential_device(config: &ClientConfig) -> Result<AuthResult> {
+    let client = create_oauth_client(config)?;
+    let auth_url_details = generate_auth_url(&client, config).await?;
     eprintln!(
         "\nUse the code {} at {} to authenticate your account",
         auth_url_details.user_code().secret().bold().green(),
@@ -40,103 +58,625 @@ pub async fn authenticate_credential_device(config: &ClientConfig) -> AuthResult
     let token_result = client
         .exchange_device_access_token(&auth_url_details)
         .request_async(async_http_client, tokio::time::sleep, None)
-        .await;
+        .await?;
 
-    let access_token = token_result.as_ref().unwrap().access_token();
-
-    process_raw_auth_result(config, access_token)
+    Ok(process_raw_auth_result(config, &token_result))
 }
 
-pub async fn authenticate_credential_password(config: &ClientConfig, username: String, password: String) -> AuthResult {
-    let client = create_oauth_client(config);
+pub async fn authenticate_credential_password(config: &ClientConfig, username: String, password: String) -> Result<AuthResult> {
+    let client = create_oauth_client(config)?;
     let token_result = client
         .exchange_password(
             &ResourceOwnerUsername::new(username),
             &ResourceOwnerPassword::new(password),
         )
-        .add_scopes(
-            config
-                .scopes
-                .split(',')
-                .map(|s| Scope::new(s.trim().to_string()))
-                .collect::<Vec<_>>(),
-        )
+        .add_scopes(delegated_scopes(config))
+        .request_async(async_http_client)
+        .await?;
+
+    Ok(process_raw_auth_result(config, &token_result))
+}
+
+pub async fn authenticate_credential_client(config: &ClientConfig) -> Result<AuthResult> {
+    let client = create_oauth_client(config)?;
+    let mut request = client
+        .exchange_client_credentials()
+        .add_scopes(app_only_scopes(config));
+
+    // Authenticate with a signed client assertion instead of a client secret
+    if let Some(certificate) = &config.certificate {
+        let token_url = client
+            .token_url()
+            .ok_or_else(|| RevelioError::Config("missing token URL".to_string()))?
+            .as_str();
+        let assertion = certificate.client_assertion(&config.client_id, token_url)?;
+        request = request
+            .add_extra_param("client_assertion_type", CLIENT_ASSERTION_TYPE)
+            .add_extra_param("client_assertion", assertion);
+    }
+
+    let token_result = request.request_async(async_http_client).await?;
+
+    Ok(process_raw_auth_result(config, &token_result))
+}
+
+pub async fn authenticate_credential_code(config: &ClientConfig, redirect_uri: String) -> Result<AuthResult> {
+    let mut redirect_url = Url::parse(&redirect_uri)?;
+    let listener = bind_loopback_listener(&mut redirect_url).await;
+    let client = create_oauth_client(config)?.set_redirect_uri(RedirectUrl::from_url(redirect_url.clone()));
+
+    // Generate a PKCE challenge and the URL where the user will authorize the client
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+    let (authorize_url, csrf_state) = client
+        .authorize_url(CsrfToken::new_random)
+        .add_scopes(delegated_scopes(config))
+        .set_pkce_challenge(pkce_challenge)
+        .url();
+
+    eprintln!(
+        "\nOpen the following URL in your browser to authenticate your account:\n{}",
+        authorize_url.as_str().blue()
+    );
+    match &listener {
+        Some(_) => eprintln!(
+            "Waiting for redirect on {} (or paste the redirect URL or code here)...",
+            redirect_url.as_str().blue()
+        ),
+        None => eprintln!("Paste the redirect URL or code here:"),
+    }
+
+    let redirect = wait_for_redirect(listener, &redirect_url).await;
+    let code = redirect_to_code(&redirect, csrf_state.secret())?;
+
+    let token_result = client
+        .exchange_code(code)
+        .set_pkce_verifier(pkce_verifier)
+        .request_async(async_http_client)
+        .await?;
+
+    Ok(process_raw_auth_result(config, &token_result))
+}
+
+/// Bind a local listener for the redirect URL if it points to the loopback
+/// interface. If no port is given, a random one is chosen and set in the URL.
+async fn bind_loopback_listener(redirect_url: &mut Url) -> Option<TcpListener> {
+    if redirect_url.scheme() != "http"
+        || !matches!(redirect_url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"))
+    {
+        return None;
+    }
+    // The browser connects to the address family of the redirect host
+    let address = if redirect_url.host_str() == Some("[::1]") {
+        "::1"
+    } else {
+        "127.0.0.1"
+    };
+    let port = redirect_url.port().unwrap_or(0);
+    match TcpListener::bind((address, port)).await {
+        Ok(listener) => {
+            if port == 0 {
+                let port = listener.local_addr().ok()?.port();
+                redirect_url.set_port(Some(port)).ok()?;
+            }
+            Some(listener)
+        }
+        Err(e) => {
+            eprintln!("Failed to listen on {}: {}", redirect_url.as_str(), e);
+            None
+        }
+    }
+}
+
+/// Wait for the authorization response, either caught by the loopback listener
+/// or pasted by the user (as a full redirect URL or as a bare code).
+async fn wait_for_redirect(listener: Option<TcpListener>, redirect_url: &Url) -> String {
+    // Read from stdin in a plain thread so it does not hold the runtime on exit
+    let (tx, rx) = oneshot::channel();
+    std::thread::spawn(move || {
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line).is_ok() {
+            let _ = tx.send(line.trim().to_string());
+        }
+    });
+
+    match listener {
+        Some(listener) => {
+            tokio::select! {
+                redirect = accept_redirect(listener, redirect_url) => redirect,
+                pasted = rx => pasted.unwrap_or_default(),
+            }
+        }
+        None => rx.await.unwrap_or_default(),
+    }
+}
+
+/// Accept connections on the loopback listener until a request carrying the
+/// authorization response arrives, and return its full URL.
+async fn accept_redirect(listener: TcpListener, redirect_url: &Url) -> String {
+    loop {
+        let Ok((mut stream, _)) = listener.accept().await else {
+            continue;
+        };
+        let mut buffer = vec![0u8; 8192];
+        let Ok(size) = stream.read(&mut buffer).await else {
+            continue;
+        };
+        let request = String::from_utf8_lossy(&buffer[..size]);
+        // Request line is in the form "GET /path?query HTTP/1.1"
+        let Some(url) = request
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|path| redirect_url.join(path).ok())
+        else {
+            continue;
+        };
+        let is_redirect = url
+            .query_pairs()
+            .any(|(key, _)| key == "code" || key == "error");
+        let body = if is_redirect {
+            "Authentication response received. You may close this window."
+        } else {
+            "Waiting for authentication response..."
+        };
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+        if is_redirect {
+            return url.to_string();
+        }
+    }
+}
+
+/// Extract the authorization code from the redirect, validating its state.
+fn redirect_to_code(redirect: &str, expected_state: &str) -> Result<AuthorizationCode> {
+    if redirect.is_empty() {
+        return Err(RevelioError::Auth("no authorization response received".to_string()));
+    }
+    let url = match Url::parse(redirect) {
+        Ok(url) => url,
+        // A bare code was pasted, so there is no state to validate
+        Err(_) => {
+            eprintln!("Warning: state could not be validated for the pasted code");
+            return Ok(AuthorizationCode::new(redirect.to_string()));
+        }
+    };
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+    };
+    if let Some(error) = param("error") {
+        return Err(RevelioError::Auth(format!(
+            "{}: {}",
+            error,
+            param("error_description").unwrap_or_default()
+        )));
+    }
+    if param("state").as_deref() != Some(expected_state) {
+        return Err(RevelioError::Auth(
+            "state mismatch in authorization response".to_string(),
+        ));
+    }
+    param("code")
+        .map(AuthorizationCode::new)
+        .ok_or_else(|| RevelioError::Auth("no authorization code in redirect URL".to_string()))
+}
+
+/// Redeem a refresh token for a new access token.
+///
+/// The returned result carries the rotated refresh token when the server
+/// issues one, or the given refresh token otherwise.
+pub async fn authenticate_refresh_token(config: &ClientConfig, refresh_token: &str) -> Result<AuthResult> {
+    let client = create_oauth_client(config)?;
+    let token_result = client
+        .exchange_refresh_token(&RefreshToken::new(refresh_token.to_string()))
+        .add_scopes(delegated_scopes(config))
         .request_async(async_http_client)
-        .await;
+        .await?;
+
+    let mut result = process_raw_auth_result(config, &token_result);
+    if result.refresh_token.is_none() {
+        result.refresh_token = Some(refresh_token.to_string());
+    }
+    Ok(result)
+}
+
+/// Result of redeeming a refresh token for a FOCI client ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FociExchangeResult {
+    pub client_name: String,
+    pub client_id: String,
+    pub resource: String,
+    pub success: bool,
+    pub scopes: Vec<String>,
+    pub access_token: Option<String>,
+    pub refresh_token: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Redeem a refresh token issued to a family-of-client-IDs (FOCI) app against
+/// other FOCI client IDs, requesting a token for each of the given resources.
+///
+/// `clients` is a list of (name, client ID) pairs.
+pub async fn exchange_foci_refresh_token(
+    config: &ClientConfig,
+    refresh_token: &str,
+    clients: &[(&str, &str)],
+    resources: &[String],
+) -> Vec<FociExchangeResult> {
+    let mut results = Vec::new();
+    for (client_name, client_id) in clients {
+        for resource in resources {
+            // FOCI clients are public clients, so no client credentials are sent
+            let mut foci_config = config.clone();
+            foci_config.client_id = client_id.to_string();
+            foci_config.client_secret = String::new();
+            foci_config.certificate = None;
+            foci_config.scopes = format!("{}/.default,offline_access", resource.trim_end_matches('/'));
 
-    let access_token = token_result.as_ref().unwrap().access_token();
+            let result = authenticate_refresh_token(&foci_config, refresh_token).await;
+            results.push(FociExchangeResult {
+                client_name: client_name.to_string(),
+                client_id: client_id.to_string(),
+                resource: resource.to_string(),
+                success: result.is_ok(),
+                scopes: result
+                    .as_ref()
+                    .ok()
+                    .and_then(|r| r.scopes.clone())
+                    .unwrap_or_default(),
+                access_token: result.as_ref().ok().and_then(|r| r.auth_token.clone()),
+                refresh_token: result.as_ref().ok().and_then(|r| r.refresh_token.clone()),
+                error: result.err().map(|e| e.to_string()),
+            });
+        }
+    }
+    results
+}
+
+/// Claims of interest in an access or ID token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenInfo {
+    pub tenant_id: Option<String>,
+    pub audience: Option<String>,
+    pub app_id: Option<String>,
+    pub app_name: Option<String>,
+    pub upn: Option<String>,
+    pub object_id: Option<String>,
+    pub scopes: Vec<String>,
+    pub roles: Vec<String>,
+    pub amr: Vec<String>,
+    pub device_id: Option<String>,
+    pub issued_at: Option<String>,
+    pub expires_at: Option<String>,
+    /// Seconds until the token expires (negative if already expired)
+    pub expires_in: Option<i64>,
+    pub claims: Value,
+}
+
+impl fmt::Display for TokenInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = [
+            ("Tenant", self.tenant_id.clone()),
+            ("Audience", self.audience.clone()),
+            ("App ID", self.app_id.clone()),
+            ("App name", self.app_name.clone()),
+            ("UPN", self.upn.clone()),
+            ("Object ID", self.object_id.clone()),
+            ("Scopes", Some(self.scopes.join(" "))),
+            ("Roles", Some(self.roles.join(" "))),
+            ("AMR", Some(self.amr.join(" "))),
+            ("Device ID", self.device_id.clone()),
+            ("Issued at", self.issued_at.clone()),
+            ("Expires at", self.expires_at.clone()),
+        ];
+        for (name, value) in fields {
+            match value {
+                Some(value) if !value.is_empty() => writeln!(f, "{:<12}{}", name, value.green())?,
+                _ => {}
+            }
+        }
+        match self.expires_in {
+            Some(seconds) if seconds > 0 => write!(
+                f,
+                "{:<12}{}",
+                "Remaining",
+                format!("{}h {}m {}s", seconds / 3600, seconds % 3600 / 60, seconds % 60).green()
+            ),
+            Some(_) => write!(f, "{:<12}{}", "Remaining", "expired".red()),
+            None => Ok(()),
+        }
+    }
+}
 
-    process_raw_auth_result(config, access_token)
+/// Decode the claims of a JWT without verifying its signature.
+pub fn decode_token_claims(token: &str) -> Result<Value> {
+    let payload = token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| RevelioError::Parse("token is not a JWT".to_string()))?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(|e| RevelioError::Parse(format!("invalid token payload: {}", e)))?;
+    Ok(serde_json::from_slice(&payload)?)
 }
 
+/// Decode an access or ID token (without verification) into its main claims.
+pub fn inspect_token(token: &str) -> Result<TokenInfo> {
+    let claims = decode_token_claims(token)?;
+    let string = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| claims[name].as_str().map(|value| value.to_string()))
+    };
+    let list = |name: &str| match &claims[name] {
+        Value::Array(values) => values
+            .iter()
+            .filter_map(|value| value.as_str().map(|value| value.to_string()))
+            .collect(),
+        Value::String(value) => value.split(' ').map(|value| value.to_string()).collect(),
+        _ => Vec::new(),
+    };
+    let time = |name: &str| {
+        claims[name]
+            .as_i64()
+            .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single())
+            .map(|datetime| datetime.to_rfc3339())
+    };
+    Ok(TokenInfo {
+        tenant_id: string(&["tid"]),
+        audience: string(&["aud"]),
+        app_id: string(&["appid", "azp"]),
+        app_name: string(&["app_displayname"]),
+        upn: string(&["upn", "unique_name", "preferred_username"]),
+        object_id: string(&["oid"]),
+        scopes: list("scp"),
+        roles: list("roles"),
+        amr: list("amr"),
+        device_id: string(&["deviceid"]),
+        issued_at: time("iat"),
+        expires_at: time("exp"),
+        expires_in: claims["exp"]
+            .as_i64()
+            .map(|exp| exp - Utc::now().timestamp()),
+        claims,
+    })
+}
+
+/// Get the scopes requested by the configured authentication flow.
+pub fn requested_scopes(config: &ClientConfig) -> Vec<String> {
+    let scopes = match config.auth_flow {
+        AuthFlow::Client => app_only_scopes(config),
+        _ => delegated_scopes(config),
+    };
+    scopes.into_iter().map(|s| s.to_string()).collect()
+}
 
 /// Create an OAuth2 client according to the given configuration.
-fn create_oauth_client(config: &ClientConfig) -> BasicClient {
+fn create_oauth_client(config: &ClientConfig) -> Result<BasicClient> {
     let auth_url = AuthUrl::new(format!(
-        "https://login.microsoftonline.com/{}/oauth2/v2.0/authorize",
-        config.tenant_id
-    ))
-    .expect("Invalid authorization URL");
+        "{}/{}/oauth2/v2.0/authorize",
+        config.authority_host, config.tenant_id
+    ))?;
     let token_url = TokenUrl::new(format!(
-        "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
-        config.tenant_id
-    ))
-    .expect("Invalid token URL");
+        "{}/{}/oauth2/v2.0/token",
+        config.authority_host, config.tenant_id
+    ))?;
     let device_auth_url = DeviceAuthorizationUrl::new(format!(
-        "https://login.microsoftonline.com/{}/oauth2/v2.0/devicecode",
-        config.tenant_id
-    ))
-    .expect("Invalid device authorization URL");
+        "{}/{}/oauth2/v2.0/devicecode",
+        config.authority_host, config.tenant_id
+    ))?;
+
+    // Public clients and certificate credentials must not send a client secret
+    let client_secret = if config.client_secret.is_empty() || config.certificate.is_some() {
+        None
+    } else {
+        Some(ClientSecret::new(config.client_secret.to_string()))
+    };
 
-    BasicClient::new(
+    Ok(BasicClient::new(
         ClientId::new(config.client_id.to_string()),
-        Some(ClientSecret::new(config.client_secret.to_string())),
+        client_secret,
         auth_url,
         Some(token_url),
     )
     // Set the device authorization URL
     .set_device_authorization_url(device_auth_url)
-    .set_auth_type(oauth2::AuthType::RequestBody)
+    .set_auth_type(oauth2::AuthType::RequestBody))
 }
 
 /// Generate the authorization URL and the user code.
 async fn generate_auth_url(
     client: &BasicClient,
     config: &ClientConfig,
-) -> StandardDeviceAuthorizationResponse {
-    let scopes = config
+) -> Result<StandardDeviceAuthorizationResponse> {
+    // Generate the URL where the user will be redirected to authorize the client.
+    let details: StandardDeviceAuthorizationResponse = client
+        .exchange_device_code()?
+        .add_scopes(delegated_scopes(config))
+        .request_async(async_http_client)
+        .await?;
+    Ok(details)
+}
+
+/// Get the scopes for a delegated token request.
+fn delegated_scopes(config: &ClientConfig) -> Vec<Scope> {
+    config
         .scopes
         .split(',')
         .map(|s| Scope::new(s.trim().to_string()))
+        .collect::<Vec<_>>()
+}
+
+/// Get the scopes for an app-only token request.
+///
+/// The client credentials flow only accepts `{resource}/.default` scopes, so
+/// delegated scopes (e.g. `User.Read`) are replaced by the Graph default scope.
+fn app_only_scopes(config: &ClientConfig) -> Vec<Scope> {
+    let scopes = config
+        .scopes
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| s.ends_with("/.default"))
+        .map(|s| Scope::new(s.to_string()))
         .collect::<Vec<_>>();
-    // Generate the URL where the user will be redirected to authorize the client.
-    let details: StandardDeviceAuthorizationResponse = client
-        .exchange_device_code()
-        .expect("Failed to exchange device code")
-        .add_scopes(scopes)
-        .request_async(async_http_client)
-        .await
-        .expect("Failed to create auth URL");
-    details
+    if scopes.is_empty() {
+        vec![Scope::new(format!("{}/.default", config.graph_host))]
+    } else {
+        scopes
+    }
 }
 
-fn process_raw_auth_result(credential: &ClientConfig, token_result: &AccessToken) -> AuthResult {
-    // Implement the processing logic here
-    AuthResult::new(credential.clone(), Some(token_result.secret().to_string()))
+fn process_raw_auth_result(credential: &ClientConfig, token_result: &BasicTokenResponse) -> AuthResult {
+    let mut result = AuthResult::new(
+        credential.clone(),
+        Some(token_result.access_token().secret().to_string()),
+    );
+    result.refresh_token = token_result
+        .refresh_token()
+        .map(|token| token.secret().to_string());
+    result.expires_at = token_result
+        .expires_in()
+        .map(|expires_in| Utc::now().timestamp() + expires_in.as_secs() as i64);
+    result.scopes = token_result
+        .scopes()
+        .map(|scopes| scopes.iter().map(|s| s.to_string()).collect());
+    result
 }
 
-pub fn export_auth_results(auth_results: &[AuthResult]) {
+pub fn export_auth_results(auth_results: &[AuthResult]) -> Result<()> {
     let export_file = format!(
         "revelio_result_{}.json",
         Utc::now().format("%Y-%m-%d_%H-%M-%S")
     );
 
-    let json_execution_plan =
-        serde_json::to_string(auth_results).expect("JSON serialization error");
+    let json_execution_plan = serde_json::to_string(auth_results)?;
 
-    let mut file = File::create(&export_file).expect("Failed to create export file");
-    file.write_all(json_execution_plan.as_bytes())
-        .expect("Failed to write to export file");
+    // Results hold tokens, so the file is only readable by the current user
+    write_private_file(Path::new(&export_file), json_execution_plan.as_bytes())?;
 
     println!("Authentication results saved to file '{}'", export_file);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirect_to_code_accepts_matching_state() {
+        let code = redirect_to_code("http://localhost:8080/?code=abc&state=xyz", "xyz").unwrap();
+        assert_eq!(code.secret(), "abc");
+    }
+
+    #[test]
+    fn redirect_to_code_rejects_state_mismatch() {
+        let result = redirect_to_code("http://localhost:8080/?code=abc&state=other", "xyz");
+        assert!(matches!(result, Err(RevelioError::Auth(message)) if message.contains("state")));
+    }
+
+    #[test]
+    fn redirect_to_code_rejects_missing_state() {
+        let result = redirect_to_code("http://localhost:8080/?code=abc", "xyz");
+        assert!(matches!(result, Err(RevelioError::Auth(_))));
+    }
+
+    #[test]
+    fn redirect_to_code_reports_authorization_error() {
+        let result = redirect_to_code(
+            "http://localhost:8080/?error=access_denied&error_description=denied&state=xyz",
+            "xyz",
+        );
+        assert!(matches!(result, Err(RevelioError::Auth(message)) if message == "access_denied: denied"));
+    }
+
+    #[test]
+    fn redirect_to_code_accepts_bare_code() {
+        let code = redirect_to_code("abc", "xyz").unwrap();
+        assert_eq!(code.secret(), "abc");
+    }
+
+    #[tokio::test]
+    async fn bind_loopback_listener_matches_ipv6_host() {
+        let mut redirect_url = Url::parse("http://[::1]/").unwrap();
+        // IPv6 may be unavailable in the test environment
+        if let Some(listener) = bind_loopback_listener(&mut redirect_url).await {
+            assert!(listener.local_addr().unwrap().is_ipv6());
+            assert_eq!(redirect_url.port(), Some(listener.local_addr().unwrap().port()));
+        }
+    }
+
+    // Unsigned JWT carrying the given claims
+    fn unsigned_token(claims: serde_json::Value) -> String {
+        format!(
+            "{}.{}.",
+            URL_SAFE_NO_PAD.encode(br#"{"alg":"none","typ":"JWT"}"#),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        )
+    }
+
+    #[test]
+    fn inspect_delegated_token() {
+        let now = Utc::now().timestamp();
+        let token = unsigned_token(serde_json::json!({
+            "tid": "tenant",
+            "aud": "https://graph.microsoft.com",
+            "appid": "app",
+            "azp": "other",
+            "app_displayname": "Graph Explorer",
+            "unique_name": "user@contoso.com",
+            "preferred_username": "other@contoso.com",
+            "scp": "openid User.Read Mail.Read",
+            "amr": ["pwd", "mfa"],
+            "iat": now - 60,
+            "exp": now + 3600,
+        }));
+        let info = inspect_token(&token).unwrap();
+        assert_eq!(info.tenant_id.as_deref(), Some("tenant"));
+        assert_eq!(info.app_id.as_deref(), Some("app"));
+        assert_eq!(info.app_name.as_deref(), Some("Graph Explorer"));
+        assert_eq!(info.upn.as_deref(), Some("user@contoso.com"));
+        assert_eq!(info.scopes, vec!["openid", "User.Read", "Mail.Read"]);
+        assert!(info.roles.is_empty());
+        assert_eq!(info.amr, vec!["pwd", "mfa"]);
+        let expires_in = info.expires_in.unwrap();
+        assert!((3598..=3600).contains(&expires_in), "{}", expires_in);
+        assert_eq!(
+            info.expires_at,
+            Utc.timestamp_opt(now + 3600, 0)
+                .single()
+                .map(|at| at.to_rfc3339())
+        );
+    }
+
+    #[test]
+    fn inspect_app_only_token() {
+        // v2 tokens name the client app `azp` and app-only ones carry roles
+        let now = Utc::now().timestamp();
+        let token = unsigned_token(serde_json::json!({
+            "azp": "app",
+            "roles": ["User.Read.All", "Directory.Read.All"],
+            "exp": now - 120,
+        }));
+        let info = inspect_token(&token).unwrap();
+        assert_eq!(info.app_id.as_deref(), Some("app"));
+        assert!(info.scopes.is_empty());
+        assert_eq!(info.roles, vec!["User.Read.All", "Directory.Read.All"]);
+        assert_eq!(info.upn, None);
+        assert_eq!(info.issued_at, None);
+        let expires_in = info.expires_in.unwrap();
+        assert!((-122..=-120).contains(&expires_in), "{}", expires_in);
+    }
+
+    #[test]
+    fn inspect_rejects_non_jwt() {
+        assert!(matches!(
+            inspect_token("opaque"),
+            Err(RevelioError::Parse(_))
+        ));
+    }
 }
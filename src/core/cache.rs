@@ -0,0 +1,247 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::auth::{decode_token_claims, AuthResult};
+use crate::error::Result;
+use crate::helpers::{create_private_dir, write_private_file};
+
+// Access tokens expiring within this margin (in seconds) are considered expired
+const EXPIRY_MARGIN: i64 = 300;
+
+/// Token cache entry, keyed by tenant, client ID, endpoints, scopes and account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenCacheEntry {
+    pub tenant_id: String,
+    pub client_id: String,
+    /// Authority host the token was issued by (entries of older caches have
+    /// none and are never reused)
+    #[serde(default)]
+    pub authority_host: String,
+    /// Microsoft Graph host the token was issued for
+    #[serde(default)]
+    pub graph_host: String,
+    pub scopes: Vec<String>,
+    pub account: String,
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// Expiration time of the access token (Unix timestamp)
+    pub expires_at: i64,
+}
+
+impl TokenCacheEntry {
+    /// Check whether the access token can still be used
+    pub fn is_valid(&self) -> bool {
+        self.expires_at - EXPIRY_MARGIN > Utc::now().timestamp()
+    }
+
+    fn key(&self) -> String {
+        cache_key(
+            &self.tenant_id,
+            &self.client_id,
+            &self.authority_host,
+            &self.graph_host,
+            &self.scopes,
+            &self.account,
+        )
+    }
+}
+
+/// Persistent token cache stored as a JSON file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TokenCache {
+    #[serde(skip)]
+    path: PathBuf,
+    entries: HashMap<String, TokenCacheEntry>,
+}
+
+impl TokenCache {
+    /// Load the token cache from the given file. A missing or unreadable
+    /// file results in an empty cache.
+    pub fn load(path: &Path) -> Self {
+        let mut cache: TokenCache = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        cache.path = path.to_path_buf();
+        cache
+    }
+
+    /// Get the path of the token cache file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write the token cache to its file, readable only by the current user.
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            create_private_dir(parent)?;
+        }
+        write_private_file(&self.path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    /// Find the most recent entry for the given tenant, client ID, authority
+    /// and Graph hosts and scopes, optionally restricted to an account.
+    pub fn find(
+        &self,
+        tenant_id: &str,
+        client_id: &str,
+        authority_host: &str,
+        graph_host: &str,
+        scopes: &[String],
+        account: Option<&str>,
+    ) -> Option<&TokenCacheEntry> {
+        let scopes = normalize_scopes(scopes);
+        self.entries
+            .values()
+            .filter(|entry| {
+                entry.tenant_id == tenant_id
+                    && entry.client_id == client_id
+                    && entry.authority_host.eq_ignore_ascii_case(authority_host)
+                    && entry.graph_host.eq_ignore_ascii_case(graph_host)
+                    && entry.scopes == scopes
+                    && account.map_or(true, |account| entry.account.eq_ignore_ascii_case(account))
+            })
+            .max_by_key(|entry| entry.expires_at)
+    }
+
+    /// Store a successful authentication result, replacing the previous entry
+    /// (and thus the previous refresh token) of the same account.
+    pub fn insert(&mut self, auth_result: &AuthResult, scopes: &[String], account: Option<&str>) {
+        let Some(access_token) = &auth_result.auth_token else {
+            return;
+        };
+        let account = account
+            .map(|account| account.to_string())
+            .or_else(|| token_account(access_token))
+            .unwrap_or_default();
+        let entry = TokenCacheEntry {
+            tenant_id: auth_result.credential.tenant_id.clone(),
+            client_id: auth_result.credential.client_id.clone(),
+            authority_host: auth_result.credential.authority_host.clone(),
+            graph_host: auth_result.credential.graph_host.clone(),
+            scopes: normalize_scopes(scopes),
+            account,
+            access_token: access_token.clone(),
+            refresh_token: auth_result.refresh_token.clone(),
+            expires_at: auth_result
+                .expires_at
+                .unwrap_or_else(|| Utc::now().timestamp()),
+        };
+        self.entries.insert(entry.key(), entry);
+    }
+
+    /// Remove the given entry from the cache
+    pub fn remove(&mut self, entry: &TokenCacheEntry) {
+        self.entries.remove(&entry.key());
+    }
+}
+
+/// Get the default location of the token cache file
+pub fn default_cache_path() -> PathBuf {
+    let home = std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .unwrap_or_else(|_| ".".to_string());
+    PathBuf::from(home).join(".revelio").join("token_cache.json")
+}
+
+fn cache_key(
+    tenant_id: &str,
+    client_id: &str,
+    authority_host: &str,
+    graph_host: &str,
+    scopes: &[String],
+    account: &str,
+) -> String {
+    format!(
+        "{}|{}|{}|{}|{}|{}",
+        tenant_id.to_lowercase(),
+        client_id.to_lowercase(),
+        authority_host.to_lowercase(),
+        graph_host.to_lowercase(),
+        scopes.join(" "),
+        account.to_lowercase()
+    )
+}
+
+fn normalize_scopes(scopes: &[String]) -> Vec<String> {
+    let mut scopes: Vec<String> = scopes.iter().map(|s| s.trim().to_lowercase()).collect();
+    scopes.sort();
+    scopes.dedup();
+    scopes
+}
+
+/// Get the account the access token was issued to, from its (unverified) claims.
+fn token_account(access_token: &str) -> Option<String> {
+    let claims = decode_token_claims(access_token).ok()?;
+    ["upn", "unique_name", "preferred_username", "oid", "appid"]
+        .iter()
+        .find_map(|claim| claims[claim].as_str().map(|value| value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::{AuthFlow, ClientConfig};
+
+    fn config(authority_host: &str, graph_host: &str) -> ClientConfig {
+        let mut config = ClientConfig::new(
+            "client".to_string(),
+            String::new(),
+            "organizations".to_string(),
+            AuthFlow::Device,
+            None,
+            "User.Read".to_string(),
+            "win_chrome_win10".to_string(),
+        );
+        config.authority_host = authority_host.to_string();
+        config.graph_host = graph_host.to_string();
+        config
+    }
+
+    #[test]
+    fn find_matches_endpoints() {
+        let global = config("https://login.microsoftonline.com", "https://graph.microsoft.com");
+        let china = config("https://login.chinacloudapi.cn", "https://microsoftgraph.chinacloudapi.cn");
+        let scopes = vec!["User.Read".to_string()];
+        let mut cache = TokenCache::default();
+        cache.insert(
+            &AuthResult::new(global.clone(), Some("token".to_string())),
+            &scopes,
+            Some("user@contoso.com"),
+        );
+
+        let find = |config: &ClientConfig| {
+            cache
+                .find(
+                    &config.tenant_id,
+                    &config.client_id,
+                    &config.authority_host,
+                    &config.graph_host,
+                    &scopes,
+                    None,
+                )
+                .is_some()
+        };
+        assert!(find(&global));
+        assert!(!find(&china));
+        assert!(!find(&config("https://login.microsoftonline.com", "http://localhost:8080")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_restricts_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir()
+            .join(format!("revelio-cache-{}", std::process::id()))
+            .join(".revelio");
+        let cache = TokenCache::load(&dir.join("token_cache.json"));
+        cache.save().unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(cache.path()), 0o600);
+        assert_eq!(mode(&dir), 0o700);
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+}
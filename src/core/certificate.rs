@@ -0,0 +1,250 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use openssl::hash::MessageDigest;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::{PKey, Private};
+use openssl::rand::rand_bytes;
+use openssl::sign::Signer;
+use openssl::x509::X509;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::Path;
+
+use crate::error::{Result, RevelioError};
+
+// Lifetime of the client assertion in seconds
+const ASSERTION_LIFETIME: i64 = 600;
+
+/// Certificate credential of an app registration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientCertificate {
+    /// Path to the certificate (PEM or PFX/PKCS#12)
+    pub path: String,
+    /// Path to the PEM private key, if not bundled with the certificate
+    pub private_key: Option<String>,
+    /// Password of the PFX file or of the encrypted PEM private key
+    pub password: Option<String>,
+}
+
+impl ClientCertificate {
+    pub fn new(path: String, private_key: Option<String>, password: Option<String>) -> Self {
+        Self {
+            path,
+            private_key,
+            password,
+        }
+    }
+
+    /// Load the certificate and its private key from disk.
+    fn load(&self) -> Result<(X509, PKey<Private>)> {
+        let bytes = std::fs::read(&self.path)?;
+        let is_pfx = Path::new(&self.path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.eq_ignore_ascii_case("pfx") || ext.eq_ignore_ascii_case("p12"))
+            .unwrap_or(false);
+        if is_pfx {
+            let parsed = Pkcs12::from_der(&bytes)?.parse2(self.password.as_deref().unwrap_or(""))?;
+            let cert = parsed
+                .cert
+                .ok_or_else(|| RevelioError::Config("no certificate found in PFX file".to_string()))?;
+            let pkey = parsed
+                .pkey
+                .ok_or_else(|| RevelioError::Config("no private key found in PFX file".to_string()))?;
+            return Ok((cert, pkey));
+        }
+        let cert = X509::from_pem(&bytes)?;
+        let key_bytes = match &self.private_key {
+            Some(path) => std::fs::read(path)?,
+            None => bytes,
+        };
+        let pkey = match &self.password {
+            Some(password) => PKey::private_key_from_pem_passphrase(&key_bytes, password.as_bytes())?,
+            None => PKey::private_key_from_pem(&key_bytes)?,
+        };
+        Ok((cert, pkey))
+    }
+
+    /// Build a signed JWT client assertion for the given token endpoint.
+    ///
+    /// See https://learn.microsoft.com/en-us/entra/identity-platform/certificate-credentials
+    pub fn client_assertion(
+        &self,
+        client_id: &str,
+        token_url: &str,
+    ) -> Result<String> {
+        let (cert, pkey) = self.load()?;
+        let thumbprint = cert.digest(MessageDigest::sha1())?;
+        let header = json!({
+            "alg": "RS256",
+            "typ": "JWT",
+            "x5t": URL_SAFE_NO_PAD.encode(thumbprint),
+        });
+
+        let mut jti = [0u8; 16];
+        rand_bytes(&mut jti)?;
+        let now = Utc::now().timestamp();
+        let claims = json!({
+            "aud": token_url,
+            "iss": client_id,
+            "sub": client_id,
+            "jti": jti.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+            "nbf": now,
+            "iat": now,
+            "exp": now + ASSERTION_LIFETIME,
+        });
+
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?)
+        );
+        let mut signer = Signer::new(MessageDigest::sha256(), &pkey)?;
+        signer.update(message.as_bytes())?;
+        let signature = signer.sign_to_vec()?;
+
+        Ok(format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::BigNum;
+    use openssl::rsa::Rsa;
+    use openssl::sign::Verifier;
+    use openssl::symm::Cipher;
+    use openssl::x509::X509NameBuilder;
+    use serde_json::Value;
+    use std::path::PathBuf;
+
+    const CLIENT_ID: &str = "00000000-0000-0000-0000-000000000001";
+    const TOKEN_URL: &str = "https://login.microsoftonline.com/contoso.com/oauth2/v2.0/token";
+
+    // Empty directory for the files of a test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "revelio-certificate-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Throwaway self-signed certificate and its private key
+    fn certificate() -> (X509, PKey<Private>) {
+        let pkey = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "revelio-test").unwrap();
+        let name = name.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        let serial = BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap();
+        builder.set_serial_number(&serial).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&pkey).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder.sign(&pkey, MessageDigest::sha256()).unwrap();
+        (builder.build(), pkey)
+    }
+
+    fn decode(part: &str) -> Value {
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(part).unwrap()).unwrap()
+    }
+
+    // Check the header, claims and signature of an assertion built from `certificate`
+    fn assert_valid_assertion(certificate: &ClientCertificate, cert: &X509) {
+        let assertion = certificate.client_assertion(CLIENT_ID, TOKEN_URL).unwrap();
+        let parts: Vec<&str> = assertion.split('.').collect();
+        assert_eq!(parts.len(), 3);
+
+        let header = decode(parts[0]);
+        assert_eq!(header["alg"], "RS256");
+        let thumbprint = cert.digest(MessageDigest::sha1()).unwrap();
+        assert_eq!(header["x5t"], URL_SAFE_NO_PAD.encode(thumbprint));
+
+        let claims = decode(parts[1]);
+        assert_eq!(claims["aud"], TOKEN_URL);
+        assert_eq!(claims["iss"], CLIENT_ID);
+        assert_eq!(claims["sub"], CLIENT_ID);
+        assert_eq!(
+            claims["exp"].as_i64().unwrap() - claims["nbf"].as_i64().unwrap(),
+            ASSERTION_LIFETIME
+        );
+
+        let public_key = cert.public_key().unwrap();
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key).unwrap();
+        verifier
+            .update(format!("{}.{}", parts[0], parts[1]).as_bytes())
+            .unwrap();
+        let signature = URL_SAFE_NO_PAD.decode(parts[2]).unwrap();
+        assert!(verifier.verify(&signature).unwrap());
+    }
+
+    #[test]
+    fn client_assertion_from_pem_bundle() {
+        let dir = temp_dir("pem");
+        let (cert, pkey) = certificate();
+        let path = dir.join("cert.pem");
+        let mut pem = cert.to_pem().unwrap();
+        pem.extend(pkey.private_key_to_pem_pkcs8().unwrap());
+        std::fs::write(&path, pem).unwrap();
+
+        let certificate = ClientCertificate::new(path.display().to_string(), None, None);
+        assert_valid_assertion(&certificate, &cert);
+    }
+
+    #[test]
+    fn client_assertion_from_pem_with_encrypted_key() {
+        let dir = temp_dir("pem-key");
+        let (cert, pkey) = certificate();
+        let cert_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+        std::fs::write(&cert_path, cert.to_pem().unwrap()).unwrap();
+        let key = pkey
+            .private_key_to_pem_pkcs8_passphrase(Cipher::aes_256_cbc(), b"secret")
+            .unwrap();
+        std::fs::write(&key_path, key).unwrap();
+
+        let certificate = ClientCertificate::new(
+            cert_path.display().to_string(),
+            Some(key_path.display().to_string()),
+            Some("secret".to_string()),
+        );
+        assert_valid_assertion(&certificate, &cert);
+    }
+
+    #[test]
+    fn client_assertion_from_pfx() {
+        let dir = temp_dir("pfx");
+        let (cert, pkey) = certificate();
+        let path = dir.join("cert.pfx");
+        let pfx = Pkcs12::builder()
+            .name("revelio-test")
+            .pkey(&pkey)
+            .cert(&cert)
+            .build2("secret")
+            .unwrap();
+        std::fs::write(&path, pfx.to_der().unwrap()).unwrap();
+
+        let certificate =
+            ClientCertificate::new(path.display().to_string(), None, Some("secret".to_string()));
+        assert_valid_assertion(&certificate, &cert);
+
+        let wrong_password =
+            ClientCertificate::new(path.display().to_string(), None, Some("wrong".to_string()));
+        assert!(wrong_password
+            .client_assertion(CLIENT_ID, TOKEN_URL)
+            .is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use super::output::OutputFormat;
+use crate::error::{Result, RevelioError};
+use crate::helpers::Resource;
+
+/// Resource fetched by a collection run, with the options selecting it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchedResource {
+    pub resource: Resource,
+    /// ID of the object whose relationship was fetched (`--id`)
+    pub id: Option<String>,
+    /// Whether nested group members were included (`--transitive`)
+    #[serde(default)]
+    pub transitive: bool,
+}
+
+/// Progress of a collection run, saved after every page so that an
+/// interrupted run can be resumed where it stopped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    #[serde(skip)]
+    path: PathBuf,
+    /// Name of the collection (e.g. `users`)
+    pub resource: String,
+    /// Resource fetched, so that a resumed run processes the remaining
+    /// pages like the first ones
+    #[serde(default)]
+    pub fetched: Option<FetchedResource>,
+    /// Query parameters given for the run
+    pub query: Vec<(String, String)>,
+    /// Link to the next page, if the collection was not fully fetched
+    pub next_link: Option<String>,
+    /// Number of items written to the output file
+    pub item_count: usize,
+    pub output_file: PathBuf,
+    pub format: OutputFormat,
+    /// Columns of CSV output, kept identical when resuming
+    #[serde(default)]
+    pub columns: Vec<String>,
+    /// Length of the output file after the last written page (in bytes)
+    pub output_len: u64,
+    /// Time of the last update (RFC 3339)
+    pub updated_at: String,
+}
+
+impl Checkpoint {
+    pub fn new(
+        path: PathBuf,
+        resource: &str,
+        query: &[(&str, String)],
+        output_file: PathBuf,
+        format: OutputFormat,
+    ) -> Self {
+        Self {
+            path,
+            resource: resource.to_string(),
+            fetched: None,
+            query: query
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect(),
+            next_link: None,
+            item_count: 0,
+            output_file,
+            format,
+            columns: Vec::new(),
+            output_len: 0,
+            updated_at: Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Record the resource fetched by the run
+    pub fn with_fetched(mut self, fetched: FetchedResource) -> Self {
+        self.fetched = Some(fetched);
+        self
+    }
+
+    /// Load a checkpoint from the given file
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let mut checkpoint: Checkpoint = serde_json::from_str(&content).map_err(|e| {
+            RevelioError::Config(format!("invalid checkpoint {:?}: {}", path, e))
+        })?;
+        checkpoint.path = path.to_path_buf();
+        Ok(checkpoint)
+    }
+
+    /// Get the path of the checkpoint file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Check whether the whole collection was fetched
+    pub fn is_complete(&self) -> bool {
+        self.next_link.is_none()
+    }
+
+    /// Record a written page and save the checkpoint. The file is replaced
+    /// atomically so that an interruption never leaves it truncated.
+    pub fn update(
+        &mut self,
+        next_link: Option<&str>,
+        item_count: usize,
+        output_len: u64,
+    ) -> Result<()> {
+        self.next_link = next_link.map(|link| link.to_string());
+        self.item_count = item_count;
+        self.output_len = output_len;
+        self.updated_at = Utc::now().to_rfc3339();
+        let temp_path = self.path.with_extension("tmp");
+        std::fs::write(&temp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
@@ -1,12 +1,116 @@
-use phf::phf_map;
+use phf::{phf_map, phf_set};
 
 // To see the full list of scopes, see
 // https://docs.microsoft.com/en-us/graph/permissions-reference
 // The following scopes does not require admin consent
 pub const DEFAULT_SCOPES: &str = "openid,profile,email,User.Read,User.ReadBasic.All";
 
+// Client assertion type used for certificate credentials
+pub const CLIENT_ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+// Redirect URI used by the authorization code flow. When no port is given,
+// a random one is chosen for the local listener.
+pub const DEFAULT_REDIRECT_URI: &str = "http://localhost";
+
 // See more client IDs at https://github.com/MarkoH17/Spray365/blob/main/modules/core/constants.py
-pub const DEFAULT_CLIENT_ID: &str = "27922004-5251-4030-b22d-91ecd9a37ea4"; // msmamservice
+pub const DEFAULT_CLIENT_ID: &str = "27922004-5251-4030-b22d-91ecd9a37ea4"; // Outlook Mobile
+
+// Authority and Microsoft Graph hosts of the national clouds
+// See https://learn.microsoft.com/en-us/graph/deployments
+pub const AUTHORITY_HOST_GLOBAL: &str = "https://login.microsoftonline.com";
+pub const AUTHORITY_HOST_US_GOV: &str = "https://login.microsoftonline.us";
+pub const AUTHORITY_HOST_CHINA: &str = "https://login.chinacloudapi.cn";
+pub const GRAPH_HOST_GLOBAL: &str = "https://graph.microsoft.com";
+pub const GRAPH_HOST_US_GOV: &str = "https://graph.microsoft.us";
+pub const GRAPH_HOST_US_GOV_DOD: &str = "https://dod-graph.microsoft.us";
+pub const GRAPH_HOST_CHINA: &str = "https://microsoftgraph.chinacloudapi.cn";
+
+// Family of client IDs (FOCI) apps, whose refresh tokens can be redeemed by
+// any other app of the family.
+// See https://github.com/secureworks/family-of-client-ids-research
+pub const FOCI_CLIENT_IDS_KEYS: [&str; 14] = [
+    "azure_cli",
+    "azure_powershell",
+    "microsoft_authenticator",
+    "microsoft_edge",
+    "microsoft_office",
+    "microsoft_teams",
+    "office_365_management",
+    "onedrive",
+    "onedrive_sync_engine",
+    "outlook_mobile",
+    "power_bi",
+    "sharepoint_android",
+    "visual_studio",
+    "windows_search",
+];
+
+pub const FOCI_CLIENT_IDS: phf::Map<&'static str, &'static str> = phf_map! {
+    "azure_cli" => "04b07795-8ddb-461a-bbee-02f9e1bf7b46",
+    "azure_powershell" => "1950a258-227b-4e31-a9cf-717495945fc2",
+    "microsoft_authenticator" => "4813382a-8fa7-425e-ab75-3b753aab3abb",
+    "microsoft_edge" => "ecd6b820-32c2-49b6-98a6-444530e5a77a",
+    "microsoft_office" => "d3590ed6-52b3-4102-aeff-aad2292ab01c",
+    "microsoft_teams" => "1fec8e78-bce4-4aaf-ab1b-5451cc387264",
+    "office_365_management" => "00b41c95-dab0-4487-9791-b9d2c32c80f2",
+    "onedrive" => "b26aadf8-566f-4478-926f-589f601d9c74",
+    "onedrive_sync_engine" => "ab9b8c07-8f02-4f72-87fa-80105867a763",
+    "outlook_mobile" => "27922004-5251-4030-b22d-91ecd9a37ea4",
+    "power_bi" => "c0d2a505-13b8-4ae0-aa9e-cddd5eab0b12",
+    "sharepoint_android" => "f05ff7c9-f75a-4acd-a3b5-f4b6a870245d",
+    "visual_studio" => "872cd9fa-d31f-45e0-9eab-6e460a02d1f1",
+    "windows_search" => "26a7ee05-5602-4d76-a7ba-eae8b7b67941",
+};
+
+// Template IDs of the built-in directory roles granting control over the
+// tenant or a path to it (e.g. by resetting credentials of administrators).
+// See https://learn.microsoft.com/en-us/entra/identity/role-based-access-control/permissions-reference
+pub const PRIVILEGED_ROLE_TEMPLATES: phf::Map<&'static str, &'static str> = phf_map! {
+    "62e90394-69f5-4237-9190-012177145e10" => "Global Administrator",
+    "e8611ab8-c189-46e8-94e1-60213ab1f814" => "Privileged Role Administrator",
+    "7be44c8a-adaf-4e2a-84d6-ab2649e08a13" => "Privileged Authentication Administrator",
+    "9b895d92-2cd3-44c7-9d02-a6ac2d5ea5c3" => "Application Administrator",
+    "158c047a-c907-4556-b7ef-446551a6b5f7" => "Cloud Application Administrator",
+    "c4e39bd9-1100-46d3-8c65-fb160da0071f" => "Authentication Administrator",
+    "b1be1c3e-b65d-4f19-8427-f6fa0d97feb9" => "Conditional Access Administrator",
+    "29232cdf-9323-42fd-ade2-1d097af3e4de" => "Exchange Administrator",
+    "fdd7a751-b60b-444a-984c-02652fe8fa1c" => "Groups Administrator",
+    "729827e3-9c14-49f7-bb1b-9608f156bbb8" => "Helpdesk Administrator",
+    "8ac3fc64-6eca-42ea-9e69-59f4c7b60eb2" => "Hybrid Identity Administrator",
+    "3a2c62db-5318-420d-8d74-23affee5d9d5" => "Intune Administrator",
+    "194ae4cb-b126-40b2-bd5b-6091b380977d" => "Security Administrator",
+    "f28a1f50-f6e7-4571-818b-6a12f2af6b6c" => "SharePoint Administrator",
+    "fe930be7-5e62-47db-91af-98c3a49a38b1" => "User Administrator",
+};
+
+// Microsoft Graph permissions (delegated or application) allowing to take
+// over the tenant, its identities or its data
+pub const DANGEROUS_PERMISSIONS: phf::Set<&'static str> = phf_set! {
+    "AppRoleAssignment.ReadWrite.All",
+    "Application.ReadWrite.All",
+    "Application.ReadWrite.OwnedBy",
+    "DelegatedPermissionGrant.ReadWrite.All",
+    "Directory.AccessAsUser.All",
+    "Directory.ReadWrite.All",
+    "Domain.ReadWrite.All",
+    "Files.ReadWrite.All",
+    "Group.ReadWrite.All",
+    "GroupMember.ReadWrite.All",
+    "Mail.ReadWrite",
+    "Mail.Send",
+    "Policy.ReadWrite.AuthenticationMethod",
+    "Policy.ReadWrite.ConditionalAccess",
+    "PrivilegedAccess.ReadWrite.AzureADGroup",
+    "PrivilegedAssignmentSchedule.ReadWrite.AzureADGroup",
+    "RoleAssignmentSchedule.ReadWrite.Directory",
+    "RoleEligibilitySchedule.ReadWrite.Directory",
+    "RoleManagement.ReadWrite.Directory",
+    "Sites.FullControl.All",
+    "Sites.ReadWrite.All",
+    "User.ReadWrite.All",
+    "UserAuthenticationMethod.ReadWrite.All",
+    "full_access_as_app",
+};
 
 pub const USER_AGENTS_KEYS: [&str; 10] = [
     "android",
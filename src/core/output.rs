@@ -0,0 +1,770 @@
+use async_trait::async_trait;
+use clap::ValueEnum;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt, BufWriter};
+
+use super::checkpoint::Checkpoint;
+use crate::error::{Result, RevelioError};
+
+// Maximum width of a table column (longer values are truncated)
+const MAX_COLUMN_WIDTH: usize = 60;
+
+/// Format of the retrieved resources
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// JSON array, written incrementally
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    /// Comma-separated values, with nested properties flattened
+    Csv,
+    /// Aligned table printed to the terminal
+    Table,
+}
+
+impl OutputFormat {
+    /// File extension used for this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Table => "txt",
+        }
+    }
+}
+
+/// Destination of the items of a collection, fed one page at a time
+#[async_trait(?Send)]
+pub trait CollectionSink {
+    /// Consume the items of one page, given the link to the next page (if any)
+    async fn write_page(&mut self, items: &[Value], next_link: Option<&str>) -> Result<()>;
+    /// Take the link to the page an interrupted run continues at, if the
+    /// sink resumes one
+    fn resume_link(&mut self) -> Option<String> {
+        None
+    }
+}
+
+#[async_trait(?Send)]
+impl CollectionSink for Vec<Value> {
+    async fn write_page(&mut self, items: &[Value], _next_link: Option<&str>) -> Result<()> {
+        self.extend_from_slice(items);
+        Ok(())
+    }
+}
+
+/// Sink transforming every item (e.g. to add computed properties) before
+/// passing it on to another sink
+pub struct MapSink<'a, F> {
+    inner: &'a mut dyn CollectionSink,
+    map: F,
+}
+
+impl<'a, F: FnMut(Value) -> Value> MapSink<'a, F> {
+    pub fn new(inner: &'a mut dyn CollectionSink, map: F) -> Self {
+        Self { inner, map }
+    }
+}
+
+#[async_trait(?Send)]
+impl<F: FnMut(Value) -> Value> CollectionSink for MapSink<'_, F> {
+    async fn write_page(&mut self, items: &[Value], next_link: Option<&str>) -> Result<()> {
+        let items: Vec<Value> = items.iter().cloned().map(&mut self.map).collect();
+        self.inner.write_page(&items, next_link).await
+    }
+
+    fn resume_link(&mut self) -> Option<String> {
+        self.inner.resume_link()
+    }
+}
+
+/// Writes the items of a collection to a file as they are fetched.
+///
+/// The file is only created once the first page arrives and is flushed after
+/// every page, so the items fetched so far survive interruptions. JSON arrays
+/// are only closed by `finish`, while NDJSON and CSV files are always valid.
+/// CSV columns are taken from `--select`, or else from the default `$select`
+/// of the resource followed by the other properties of the first page, and do
+/// not change afterwards: columns first found on later pages are left out
+/// with a warning. Tables need every row to align the columns, so they are
+/// kept in memory and printed by `finish` instead of being written to a file.
+///
+/// With a checkpoint, the progress is saved after every page so that the run
+/// can be resumed with `resume`. A resumed writer gives the link to the next
+/// page of the run as its resume link.
+pub struct CollectionWriter {
+    path: PathBuf,
+    format: OutputFormat,
+    select: Option<String>,
+    default_select: Option<String>,
+    columns: Vec<String>,
+    // Columns found after the CSV header was written, already warned about
+    late_columns: Vec<String>,
+    writer: Option<BufWriter<File>>,
+    rows: Vec<Value>,
+    count: usize,
+    // Bytes written to the file so far
+    len: u64,
+    checkpoint: Option<Checkpoint>,
+    resume_link: Option<String>,
+}
+
+impl CollectionWriter {
+    pub fn new(path: PathBuf, format: OutputFormat, select: Option<String>) -> Self {
+        Self {
+            path,
+            format,
+            select,
+            default_select: None,
+            columns: Vec::new(),
+            late_columns: Vec::new(),
+            writer: None,
+            rows: Vec::new(),
+            count: 0,
+            len: 0,
+            checkpoint: None,
+            resume_link: None,
+        }
+    }
+
+    /// Order the columns after the properties selected by default for the
+    /// resource, when no `--select` is given
+    pub fn with_default_select(mut self, select: Option<&str>) -> Self {
+        self.default_select = select.map(|select| select.to_string());
+        self
+    }
+
+    /// Save the progress of the run to the given checkpoint
+    pub fn with_checkpoint(mut self, checkpoint: Checkpoint) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// Continue writing the output of an interrupted run. Anything written
+    /// after the last checkpointed page (such as a partial item or the end
+    /// of a JSON array) is discarded.
+    pub async fn resume(checkpoint: Checkpoint) -> Result<Self> {
+        if checkpoint.format == OutputFormat::Table {
+            return Err(RevelioError::Config(
+                "table output cannot be resumed".to_string(),
+            ));
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .open(&checkpoint.output_file)
+            .await?;
+        if file.metadata().await?.len() < checkpoint.output_len {
+            return Err(RevelioError::Config(format!(
+                "output file {:?} is shorter than recorded in the checkpoint",
+                checkpoint.output_file
+            )));
+        }
+        file.set_len(checkpoint.output_len).await?;
+        let mut writer = BufWriter::new(file);
+        writer.seek(SeekFrom::End(0)).await?;
+        Ok(Self {
+            path: checkpoint.output_file.clone(),
+            format: checkpoint.format,
+            select: checkpoint
+                .query
+                .iter()
+                .find(|(key, _)| key == "$select")
+                .map(|(_, select)| select.clone()),
+            default_select: None,
+            columns: checkpoint.columns.clone(),
+            late_columns: Vec::new(),
+            writer: Some(writer),
+            rows: Vec::new(),
+            count: checkpoint.item_count,
+            len: checkpoint.output_len,
+            resume_link: checkpoint.next_link.clone(),
+            checkpoint: Some(checkpoint),
+        })
+    }
+
+    /// Path of the output file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of items written so far
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Get the checkpoint the progress is saved to, if any
+    pub fn checkpoint(&self) -> Option<&Checkpoint> {
+        self.checkpoint.as_ref()
+    }
+
+    /// Terminate the output, returning the path of the file if anything was written
+    pub async fn finish(mut self) -> Result<Option<PathBuf>> {
+        if self.format == OutputFormat::Table {
+            if !self.rows.is_empty() {
+                print!(
+                    "{}",
+                    render_table(&self.rows, &self.page_columns(&self.rows))
+                );
+            }
+            return Ok(None);
+        }
+        let Some(mut writer) = self.writer.take() else {
+            return Ok(None);
+        };
+        if self.format == OutputFormat::Json {
+            writer.write_all(b"\n]\n").await?;
+        }
+        writer.flush().await?;
+        Ok(Some(self.path))
+    }
+
+    // Columns of the given items, following the selected properties
+    fn page_columns(&self, items: &[Value]) -> Vec<String> {
+        if self.select.is_some() {
+            return columns(items, self.select.as_deref());
+        }
+        let Some(default_select) = &self.default_select else {
+            return columns(items, None);
+        };
+        // Keep the properties added to the selected ones (e.g. flags)
+        let mut selected = columns(items, Some(default_select));
+        for column in columns(items, None) {
+            if !selected.contains(&column) {
+                selected.push(column);
+            }
+        }
+        selected
+    }
+}
+
+#[async_trait(?Send)]
+impl CollectionSink for CollectionWriter {
+    async fn write_page(&mut self, items: &[Value], next_link: Option<&str>) -> Result<()> {
+        // Table columns are aligned on every row by `finish`
+        if self.format == OutputFormat::Table {
+            self.rows.extend_from_slice(items);
+            self.count += items.len();
+            return Ok(());
+        }
+        if self.columns.is_empty() {
+            self.columns = self.page_columns(items);
+        } else if self.format == OutputFormat::Csv {
+            let late: Vec<String> = self
+                .page_columns(items)
+                .into_iter()
+                .filter(|column| {
+                    !self.columns.contains(column) && !self.late_columns.contains(column)
+                })
+                .collect();
+            if !late.is_empty() {
+                // Break the progress line
+                eprintln!(
+                    "\n{}",
+                    format!(
+                        "Columns not in the CSV header are left out: {}",
+                        late.join(", ")
+                    )
+                    .yellow()
+                );
+                self.late_columns.extend(late);
+            }
+        }
+        if self.writer.is_none() {
+            let mut writer = BufWriter::new(File::create(&self.path).await?);
+            let header = match self.format {
+                OutputFormat::Json => "[".to_string(),
+                OutputFormat::Csv => csv_row(&self.columns),
+                _ => String::new(),
+            };
+            writer.write_all(header.as_bytes()).await?;
+            self.len += header.len() as u64;
+            self.writer = Some(writer);
+        }
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        for item in items {
+            let line = match self.format {
+                OutputFormat::Json => {
+                    // Indent the item so the array looks like a pretty-printed one
+                    let separator = if self.count == 0 { "\n  " } else { ",\n  " };
+                    let item = serde_json::to_string_pretty(item)?.replace('\n', "\n  ");
+                    format!("{}{}", separator, item)
+                }
+                OutputFormat::Ndjson => format!("{}\n", serde_json::to_string(item)?),
+                _ => csv_row(&row(item, &self.columns)),
+            };
+            writer.write_all(line.as_bytes()).await?;
+            self.len += line.len() as u64;
+            self.count += 1;
+        }
+        writer.flush().await?;
+        if let Some(checkpoint) = &mut self.checkpoint {
+            checkpoint.columns = self.columns.clone();
+            checkpoint.update(next_link, self.count, self.len)?;
+        }
+        Ok(())
+    }
+
+    fn resume_link(&mut self) -> Option<String> {
+        self.resume_link.take()
+    }
+}
+
+/// Render items in the given format, for printing to the terminal
+pub fn render_items(items: &[Value], format: OutputFormat, select: Option<&str>) -> Result<String> {
+    let columns = columns(items, select);
+    Ok(match format {
+        OutputFormat::Json => format!("{}\n", serde_json::to_string_pretty(items)?),
+        OutputFormat::Ndjson => items
+            .iter()
+            .map(|item| Ok(format!("{}\n", serde_json::to_string(item)?)))
+            .collect::<Result<String>>()?,
+        OutputFormat::Csv => {
+            let mut csv = csv_row(&columns);
+            for item in items {
+                csv.push_str(&csv_row(&row(item, &columns)));
+            }
+            csv
+        }
+        OutputFormat::Table => render_table(items, &columns),
+    })
+}
+
+/// Flatten an item into `(column, value)` pairs. Nested objects become
+/// dotted columns (e.g. `signInActivity.lastSignInDateTime`) and arrays are
+/// joined with `;`.
+pub fn flatten(item: &Value) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    flatten_into(None, item, &mut fields);
+    fields
+}
+
+fn flatten_into(prefix: Option<&str>, value: &Value, fields: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                // Skip OData annotations such as @odata.type
+                if key.starts_with('@') {
+                    continue;
+                }
+                let column = match prefix {
+                    Some(prefix) => format!("{}.{}", prefix, key),
+                    None => key.clone(),
+                };
+                flatten_into(Some(&column), value, fields);
+            }
+        }
+        _ => {
+            if let Some(prefix) = prefix {
+                fields.push((prefix.to_string(), cell(value)));
+            }
+        }
+    }
+}
+
+// Text of a single (non-object) value
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::Object(_) | Value::Array(_) => value.to_string(),
+                _ => cell(value),
+            })
+            .collect::<Vec<String>>()
+            .join(";"),
+        _ => value.to_string(),
+    }
+}
+
+/// Columns of the flattened items, in the order of `select` if given (a
+/// selected object property expands to all of its nested columns) or in
+/// order of appearance otherwise
+pub fn columns(items: &[Value], select: Option<&str>) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+    for item in items {
+        for (column, _) in flatten(item) {
+            if !found.contains(&column) {
+                found.push(column);
+            }
+        }
+    }
+    // A property null on some items and an object on others keeps only
+    // the nested columns
+    let nested: Vec<String> = found
+        .iter()
+        .filter(|column| {
+            let prefix = format!("{}.", column);
+            found.iter().any(|other| other.starts_with(&prefix))
+        })
+        .cloned()
+        .collect();
+    found.retain(|column| !nested.contains(column));
+    let Some(select) = select else {
+        return found;
+    };
+    let mut columns = Vec::new();
+    for property in select.split(',').map(|property| property.trim()) {
+        let nested = format!("{}.", property);
+        let matching: Vec<&String> = found
+            .iter()
+            .filter(|column| column.as_str() == property || column.starts_with(&nested))
+            .collect();
+        if matching.is_empty() {
+            // Keep selected properties even if no item has them
+            columns.push(property.to_string());
+        }
+        columns.extend(matching.into_iter().cloned());
+    }
+    columns
+}
+
+// Values of an item for the given columns
+fn row(item: &Value, columns: &[String]) -> Vec<String> {
+    let fields = flatten(item);
+    columns
+        .iter()
+        .map(|column| {
+            fields
+                .iter()
+                .find(|(name, _)| name == column)
+                .map(|(_, value)| value.clone())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+// Format a CSV record, quoting fields when needed
+fn csv_row(fields: &[String]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect();
+    format!("{}\n", fields.join(","))
+}
+
+/// Render items as an aligned table with a highlighted header
+pub fn render_table(items: &[Value], columns: &[String]) -> String {
+    let rows: Vec<Vec<String>> = items
+        .iter()
+        .map(|item| {
+            row(item, columns)
+                .into_iter()
+                .map(|value| truncate(&value.replace(['\n', '\r'], " ")))
+                .collect()
+        })
+        .collect();
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain(std::iter::once(truncate(column).chars().count()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let pad = |text: &str, width: usize| {
+        format!("{}{}", text, " ".repeat(width - text.chars().count()))
+    };
+    let header: Vec<String> = columns
+        .iter()
+        .zip(&widths)
+        .map(|(column, width)| pad(&truncate(column), *width))
+        .collect();
+    let separator: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    let mut table = format!(
+        "{}\n{}\n",
+        header.join("  ").trim_end().bold(),
+        separator.join("  ")
+    );
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(value, width)| pad(value, *width))
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+// Shorten text longer than the maximum column width
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= MAX_COLUMN_WIDTH {
+        return text.to_string();
+    }
+    let mut text: String = text.chars().take(MAX_COLUMN_WIDTH - 1).collect();
+    text.push('…');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Empty directory for the files of a test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("revelio-output-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn users(ids: std::ops::Range<u32>) -> Vec<Value> {
+        ids.map(|id| json!({"id": id.to_string(), "displayName": format!("User, {}", id)}))
+            .collect()
+    }
+
+    // Write a page, then an interrupted partial page, and resume from the checkpoint
+    async fn interrupt_and_resume(format: OutputFormat) -> String {
+        let dir = temp_dir(format.extension());
+        let output_file = dir.join(format!("users.{}", format.extension()));
+        let checkpoint_path = dir.join("users.checkpoint.json");
+        let checkpoint = Checkpoint::new(
+            checkpoint_path.clone(),
+            "users",
+            &[],
+            output_file.clone(),
+            format,
+        );
+        let mut writer =
+            CollectionWriter::new(output_file.clone(), format, None).with_checkpoint(checkpoint);
+        writer
+            .write_page(&users(0..2), Some("https://graph/next"))
+            .await
+            .unwrap();
+        drop(writer);
+        // Garbage left by an interruption in the middle of the next page
+        let mut content = std::fs::read(&output_file).unwrap();
+        content.extend_from_slice(b",\n  {\"id\": \"2\", \"displ");
+        std::fs::write(&output_file, content).unwrap();
+
+        let checkpoint = Checkpoint::load(&checkpoint_path).unwrap();
+        assert_eq!(checkpoint.item_count, 2);
+        assert_eq!(checkpoint.next_link.as_deref(), Some("https://graph/next"));
+        let mut writer = CollectionWriter::resume(checkpoint).await.unwrap();
+        assert_eq!(writer.resume_link().as_deref(), Some("https://graph/next"));
+        assert_eq!(writer.resume_link(), None);
+        writer.write_page(&users(2..4), None).await.unwrap();
+        assert_eq!(writer.count(), 4);
+        assert!(writer.checkpoint().unwrap().is_complete());
+        writer.finish().await.unwrap();
+        let content = std::fs::read_to_string(&output_file).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        content
+    }
+
+    #[tokio::test]
+    async fn resume_json_discards_partial_page() {
+        let content = interrupt_and_resume(OutputFormat::Json).await;
+        let items: Vec<Value> = serde_json::from_str(&content).unwrap();
+        assert_eq!(items, users(0..4));
+    }
+
+    #[tokio::test]
+    async fn resume_ndjson_discards_partial_page() {
+        let content = interrupt_and_resume(OutputFormat::Ndjson).await;
+        let items: Vec<Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(items, users(0..4));
+    }
+
+    #[tokio::test]
+    async fn resume_csv_keeps_header() {
+        let content = interrupt_and_resume(OutputFormat::Csv).await;
+        assert_eq!(
+            content,
+            "displayName,id\n\"User, 0\",0\n\"User, 1\",1\n\"User, 2\",2\n\"User, 3\",3\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn csv_columns_follow_default_select() {
+        let dir = temp_dir("default-select");
+        let output_file = dir.join("users.csv");
+        let mut writer = CollectionWriter::new(output_file.clone(), OutputFormat::Csv, None)
+            .with_default_select(Some("id,displayName,signInActivity"));
+        let first = vec![json!({"flags": ["no-mfa"], "displayName": "A", "id": "1"})];
+        writer
+            .write_page(&first, Some("https://graph/next"))
+            .await
+            .unwrap();
+        // Nested properties first found on a later page cannot be added
+        let second = vec![json!({
+            "id": "2",
+            "displayName": "B",
+            "signInActivity": {"lastSignInDateTime": "2024-01-01T00:00:00Z"},
+            "flags": [],
+        })];
+        writer.write_page(&second, None).await.unwrap();
+        assert_eq!(
+            writer.late_columns,
+            vec!["signInActivity.lastSignInDateTime"]
+        );
+        writer.finish().await.unwrap();
+        let content = std::fs::read_to_string(&output_file).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        assert_eq!(
+            content,
+            "id,displayName,signInActivity,flags\n1,A,,no-mfa\n2,B,,\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn table_columns_span_all_pages() {
+        let mut writer = CollectionWriter::new(PathBuf::new(), OutputFormat::Table, None);
+        writer
+            .write_page(&[json!({"id": "1", "sign": null})], None)
+            .await
+            .unwrap();
+        writer
+            .write_page(&[json!({"id": "2", "sign": {"last": "x"}})], None)
+            .await
+            .unwrap();
+        assert_eq!(writer.page_columns(&writer.rows), vec!["id", "sign.last"]);
+    }
+
+    #[tokio::test]
+    async fn resume_rejects_truncated_output() {
+        let dir = temp_dir("truncated");
+        let output_file = dir.join("users.json");
+        let checkpoint_path = dir.join("users.checkpoint.json");
+        let checkpoint = Checkpoint::new(
+            checkpoint_path.clone(),
+            "users",
+            &[],
+            output_file.clone(),
+            OutputFormat::Json,
+        );
+        let mut writer = CollectionWriter::new(output_file.clone(), OutputFormat::Json, None)
+            .with_checkpoint(checkpoint);
+        writer
+            .write_page(&users(0..2), Some("https://graph/next"))
+            .await
+            .unwrap();
+        drop(writer);
+        std::fs::write(&output_file, "[").unwrap();
+
+        let checkpoint = Checkpoint::load(&checkpoint_path).unwrap();
+        let result = CollectionWriter::resume(checkpoint).await;
+        assert!(matches!(result, Err(RevelioError::Config(_))));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn flatten_nested_objects_and_arrays() {
+        let item = json!({
+            "@odata.type": "#microsoft.graph.user",
+            "id": "1",
+            "accountEnabled": true,
+            "manager": null,
+            "signInActivity": {"lastSignInDateTime": "2023-01-01", "@odata.etag": "x"},
+            "businessPhones": ["+1 555", "+1 556"],
+            "identities": [{"issuer": "contoso.com"}],
+            "empty": [],
+        });
+        assert_eq!(
+            flatten(&item),
+            vec![
+                ("accountEnabled".to_string(), "true".to_string()),
+                ("businessPhones".to_string(), "+1 555;+1 556".to_string()),
+                ("empty".to_string(), String::new()),
+                ("id".to_string(), "1".to_string()),
+                (
+                    "identities".to_string(),
+                    r#"{"issuer":"contoso.com"}"#.to_string()
+                ),
+                ("manager".to_string(), String::new()),
+                (
+                    "signInActivity.lastSignInDateTime".to_string(),
+                    "2023-01-01".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn columns_in_order_of_appearance() {
+        let items = vec![
+            json!({"id": "1", "b": {"c": 1}}),
+            json!({"id": "2", "a": 1}),
+        ];
+        assert_eq!(columns(&items, None), vec!["b.c", "id", "a"]);
+    }
+
+    #[test]
+    fn columns_follow_select() {
+        let items = vec![json!({"id": "1", "mail": null, "sign": {"last": "x", "first": "y"}})];
+        assert_eq!(
+            columns(&items, Some("sign, id,missing")),
+            vec!["sign.first", "sign.last", "id", "missing"]
+        );
+    }
+
+    #[test]
+    fn csv_row_quotes_special_characters() {
+        let fields = vec![
+            "plain".to_string(),
+            "a,b".to_string(),
+            "say \"hi\"".to_string(),
+            "two\nlines".to_string(),
+            String::new(),
+        ];
+        assert_eq!(
+            csv_row(&fields),
+            "plain,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\",\n"
+        );
+    }
+
+    #[test]
+    fn render_csv_rows_match_columns() {
+        let items = vec![
+            json!({"id": "1", "name": "A, B"}),
+            json!({"id": "2", "extra": "x"}),
+        ];
+        let csv = render_items(&items, OutputFormat::Csv, Some("id,name")).unwrap();
+        assert_eq!(csv, "id,name\n1,\"A, B\"\n2,\n");
+    }
+
+    #[test]
+    fn render_table_aligns_and_truncates() {
+        colored::control::set_override(false);
+        let long = "x".repeat(MAX_COLUMN_WIDTH + 5);
+        let items = vec![
+            json!({"id": "1", "name": "multi\nline"}),
+            json!({"id": "22", "name": long}),
+        ];
+        let columns = vec!["id".to_string(), "name".to_string()];
+        let table = render_table(&items, &columns);
+        let lines: Vec<&str> = table.lines().collect();
+        let truncated = format!("{}…", "x".repeat(MAX_COLUMN_WIDTH - 1));
+        assert_eq!(lines[0], "id  name");
+        assert_eq!(lines[1], format!("--  {}", "-".repeat(MAX_COLUMN_WIDTH)));
+        assert_eq!(lines[2], "1   multi line");
+        assert_eq!(lines[3], format!("22  {}", truncated));
+    }
+}
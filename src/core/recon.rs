@@ -0,0 +1,184 @@
+use crate::core::constants::USER_AGENTS;
+use crate::error::{Result, RevelioError};
+use crate::helpers::ClientConfig;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Tenant information gathered without authentication from the public
+/// endpoints of the authority host.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconResult {
+    pub domain: String,
+    /// Whether the domain belongs to an Entra ID tenant
+    pub exists: bool,
+    pub tenant_id: Option<String>,
+    /// Cloud instance of the tenant (e.g. microsoftonline.com)
+    pub cloud_instance: Option<String>,
+    /// Region of the tenant (e.g. EU, NA)
+    pub tenant_region_scope: Option<String>,
+    pub tenant_region_sub_scope: Option<String>,
+    /// Managed, Federated or Unknown
+    pub namespace_type: Option<String>,
+    pub federation_brand_name: Option<String>,
+    /// Protocol of the federation server (e.g. WSTrust, SAML20)
+    pub federation_protocol: Option<String>,
+    /// Sign-in URL of the federation server (e.g. ADFS) for federated domains
+    pub federation_auth_url: Option<String>,
+    /// Whether Seamless SSO (desktop SSO) is enabled
+    pub desktop_sso: Option<bool>,
+}
+
+/// Look up the tenant of a domain through its OpenID configuration, its
+/// user realm and the credential type of a user of the domain. Only the
+/// OpenID configuration is required; the other lookups are skipped with a
+/// warning when they fail.
+pub async fn recon_domain(
+    config: &ClientConfig,
+    domain: &str,
+    proxy: Option<&str>,
+    nossl: bool,
+) -> Result<ReconResult> {
+    let valid = !domain.is_empty()
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-.".contains(c));
+    if !valid {
+        return Err(RevelioError::Config(format!("invalid domain `{}`", domain)));
+    }
+    let mut builder = Client::builder()
+        .user_agent(USER_AGENTS[config.user_agent.as_str()])
+        .danger_accept_invalid_certs(nossl);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(
+            reqwest::Proxy::all(proxy)
+                .map_err(|e| RevelioError::Config(format!("invalid proxy: {}", e)))?,
+        );
+    }
+    let client = builder.build()?;
+    // Any user of the domain works, it does not have to exist
+    let username = format!("user@{}", domain);
+
+    let openid_url = format!(
+        "{}/{}/v2.0/.well-known/openid-configuration",
+        config.authority_host, domain
+    );
+    let response = client.get(openid_url).send().await?;
+    let status = response.status();
+    let openid: Value = response.json().await?;
+    // Unknown domains are rejected with HTTP 400 (AADSTS90002)
+    let exists = status.is_success();
+    if !exists && !status.is_client_error() {
+        return Err(RevelioError::from_response(status, &Default::default(), &openid));
+    }
+    let text = |value: &Value| value.as_str().map(|s| s.to_string());
+
+    let realm_url = format!("{}/getuserrealm.srf", config.authority_host);
+    let realm = match get_json(
+        client
+            .get(realm_url)
+            .query(&[("login", username.as_str()), ("json", "1")]),
+    )
+    .await
+    {
+        Ok(realm) => realm,
+        Err(e) => {
+            eprintln!("Failed to get user realm: {}", e);
+            Value::Null
+        }
+    };
+
+    // The legacy realm endpoint does not tell the federation protocol
+    let federation_protocol = if realm["NameSpaceType"] == "Federated" {
+        let realm_url = format!("{}/common/userrealm/{}", config.authority_host, username);
+        match get_json(client.get(realm_url).query(&[("api-version", "2.1")])).await {
+            Ok(realm) => text(&realm["federation_protocol"]),
+            Err(e) => {
+                eprintln!("Failed to get federation protocol: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let credential_type_url = format!("{}/common/GetCredentialType", config.authority_host);
+    let credential_type = match get_json(client.post(credential_type_url).json(&json!({
+        "username": username,
+        "isOtherIdpSupported": true,
+        "checkPhones": false,
+        "isRemoteNGCSupported": false,
+        "isCookieBannerShown": false,
+        "isFidoSupported": false,
+    })))
+    .await
+    {
+        Ok(credential_type) => credential_type,
+        Err(e) => {
+            eprintln!("Failed to get credential type: {}", e);
+            Value::Null
+        }
+    };
+
+    Ok(ReconResult {
+        domain: domain.to_string(),
+        exists,
+        tenant_id: openid["issuer"].as_str().and_then(tenant_id_from_issuer),
+        cloud_instance: text(&openid["cloud_instance_name"])
+            .or_else(|| text(&realm["CloudInstanceName"])),
+        tenant_region_scope: text(&openid["tenant_region_scope"]),
+        tenant_region_sub_scope: text(&openid["tenant_region_sub_scope"]),
+        namespace_type: text(&realm["NameSpaceType"]),
+        federation_brand_name: text(&realm["FederationBrandName"]),
+        federation_protocol,
+        federation_auth_url: text(&realm["AuthURL"]),
+        desktop_sso: credential_type["EstsProperties"]["DesktopSsoEnabled"].as_bool(),
+    })
+}
+
+async fn get_json(request: reqwest::RequestBuilder) -> Result<Value> {
+    let response = request.send().await?.error_for_status()?;
+    Ok(response.json().await?)
+}
+
+/// Extract the tenant ID from an issuer such as
+/// `https://login.microsoftonline.com/{tenant ID}/v2.0` or
+/// `https://sts.windows.net/{tenant ID}/`
+fn tenant_id_from_issuer(issuer: &str) -> Option<String> {
+    let url = reqwest::Url::parse(issuer).ok()?;
+    url.path_segments()?
+        .next()
+        .filter(|id| !id.is_empty())
+        .map(|id| id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tenant_id_from_v2_issuer() {
+        assert_eq!(
+            tenant_id_from_issuer(
+                "https://login.microsoftonline.com/72f988bf-86f1-41af-91ab-2d7cd011db47/v2.0"
+            )
+            .as_deref(),
+            Some("72f988bf-86f1-41af-91ab-2d7cd011db47")
+        );
+    }
+
+    #[test]
+    fn tenant_id_from_v1_issuer() {
+        assert_eq!(
+            tenant_id_from_issuer("https://sts.windows.net/72f988bf-86f1-41af-91ab-2d7cd011db47/")
+                .as_deref(),
+            Some("72f988bf-86f1-41af-91ab-2d7cd011db47")
+        );
+    }
+
+    #[test]
+    fn tenant_id_from_issuer_without_id() {
+        assert_eq!(tenant_id_from_issuer("https://sts.windows.net/"), None);
+        assert_eq!(tenant_id_from_issuer(""), None);
+    }
+}
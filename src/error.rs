@@ -0,0 +1,286 @@
+use oauth2::{ErrorResponseType, RequestTokenError, StandardErrorResponse};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+
+/// Error body returned by Microsoft Graph.
+///
+/// See https://learn.microsoft.com/en-us/graph/errors
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphError {
+    pub status: u16,
+    pub code: String,
+    pub message: String,
+    pub request_id: Option<String>,
+    pub client_request_id: Option<String>,
+}
+
+impl GraphError {
+    /// Parse the `error` object of a Graph response body
+    pub fn new(status: StatusCode, body: &Value) -> Self {
+        let error = &body["error"];
+        let inner_error = &error["innerError"];
+        GraphError {
+            status: status.as_u16(),
+            code: error["code"]
+                .as_str()
+                .unwrap_or(status.canonical_reason().unwrap_or("UnknownError"))
+                .to_string(),
+            message: error["message"].as_str().unwrap_or_default().to_string(),
+            request_id: inner_error["request-id"].as_str().map(|id| id.to_string()),
+            client_request_id: inner_error["client-request-id"]
+                .as_str()
+                .map(|id| id.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (HTTP {})", self.code, self.status)?;
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        if let Some(request_id) = &self.request_id {
+            write!(f, " [request-id: {}", request_id)?;
+            if let Some(client_request_id) = &self.client_request_id {
+                write!(f, ", client-request-id: {}", client_request_id)?;
+            }
+            write!(f, "]")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum RevelioError {
+    /// Invalid configuration or arguments (e.g. URLs, certificates)
+    Config(String),
+    /// Failure to obtain or use an access token
+    Auth(String),
+    /// Error response returned by Microsoft Graph
+    Graph(GraphError),
+    /// Request throttled by Microsoft Graph (HTTP 429/503/504)
+    Throttled {
+        error: GraphError,
+        /// Seconds to wait before retrying, from the Retry-After header
+        retry_after: Option<u64>,
+    },
+    /// Failure to reach the server
+    Network(reqwest::Error),
+    /// Unexpected response content
+    Parse(String),
+    /// Failure to read or write local files
+    Io(std::io::Error),
+    /// Run interrupted by the user (Ctrl-C)
+    Interrupted,
+}
+
+impl RevelioError {
+    /// Build an error from a failed Graph response
+    pub fn from_response(status: StatusCode, headers: &HeaderMap, body: &Value) -> Self {
+        let error = GraphError::new(status, body);
+        match status {
+            StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT => RevelioError::Throttled {
+                error,
+                retry_after: headers
+                    .get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.trim().parse().ok()),
+            },
+            _ => RevelioError::Graph(error),
+        }
+    }
+
+    /// Exit code of the process when failing with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RevelioError::Config(_) => 2,
+            RevelioError::Auth(_) => 3,
+            RevelioError::Graph(_) => 4,
+            RevelioError::Throttled { .. } => 5,
+            RevelioError::Network(_) => 6,
+            RevelioError::Parse(_) => 7,
+            RevelioError::Io(_) => 8,
+            RevelioError::Interrupted => 130,
+        }
+    }
+}
+
+impl fmt::Display for RevelioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevelioError::Config(message) => write!(f, "Invalid configuration: {}", message),
+            RevelioError::Auth(message) => write!(f, "Authentication failed: {}", message),
+            RevelioError::Graph(error) => write!(f, "Graph API error: {}", error),
+            RevelioError::Throttled { error, retry_after } => {
+                write!(f, "Request throttled: {}", error)?;
+                match retry_after {
+                    Some(seconds) => write!(f, " (retry after {}s)", seconds),
+                    None => Ok(()),
+                }
+            }
+            RevelioError::Network(error) => write!(f, "Network error: {}", error),
+            RevelioError::Parse(message) => write!(f, "Unexpected response: {}", message),
+            RevelioError::Io(error) => write!(f, "I/O error: {}", error),
+            RevelioError::Interrupted => write!(f, "Interrupted"),
+        }
+    }
+}
+
+impl std::error::Error for RevelioError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RevelioError::Network(error) => Some(error),
+            RevelioError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for RevelioError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_decode() {
+            RevelioError::Parse(error.to_string())
+        } else {
+            RevelioError::Network(error)
+        }
+    }
+}
+
+impl From<serde_json::Error> for RevelioError {
+    fn from(error: serde_json::Error) -> Self {
+        RevelioError::Parse(error.to_string())
+    }
+}
+
+impl From<std::io::Error> for RevelioError {
+    fn from(error: std::io::Error) -> Self {
+        RevelioError::Io(error)
+    }
+}
+
+impl From<oauth2::url::ParseError> for RevelioError {
+    fn from(error: oauth2::url::ParseError) -> Self {
+        RevelioError::Config(format!("invalid URL: {}", error))
+    }
+}
+
+impl From<openssl::error::ErrorStack> for RevelioError {
+    fn from(error: openssl::error::ErrorStack) -> Self {
+        RevelioError::Config(format!("certificate error: {}", error))
+    }
+}
+
+impl From<oauth2::ConfigurationError> for RevelioError {
+    fn from(error: oauth2::ConfigurationError) -> Self {
+        RevelioError::Config(error.to_string())
+    }
+}
+
+impl<T> From<RequestTokenError<oauth2::reqwest::Error<reqwest::Error>, StandardErrorResponse<T>>>
+    for RevelioError
+where
+    T: ErrorResponseType + fmt::Display + 'static,
+{
+    fn from(
+        error: RequestTokenError<oauth2::reqwest::Error<reqwest::Error>, StandardErrorResponse<T>>,
+    ) -> Self {
+        match error {
+            RequestTokenError::ServerResponse(response) => RevelioError::Auth(response.to_string()),
+            RequestTokenError::Request(oauth2::reqwest::Error::Reqwest(error)) => {
+                RevelioError::Network(error)
+            }
+            RequestTokenError::Parse(error, _) => RevelioError::Parse(error.to_string()),
+            error => RevelioError::Auth(error.to_string()),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, RevelioError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn graph_error(status: u16, retry_after: Option<&str>) -> RevelioError {
+        let mut headers = HeaderMap::new();
+        if let Some(retry_after) = retry_after {
+            headers.insert(RETRY_AFTER, retry_after.parse().unwrap());
+        }
+        let status = StatusCode::from_u16(status).unwrap();
+        RevelioError::from_response(status, &headers, &Value::Null)
+    }
+
+    #[test]
+    fn graph_error_parses_body() {
+        let body = json!({"error": {
+            "code": "Authorization_RequestDenied",
+            "message": "Insufficient privileges to complete the operation.",
+            "innerError": {"request-id": "req", "client-request-id": "client"},
+        }});
+        let error = GraphError::new(StatusCode::FORBIDDEN, &body);
+        assert_eq!(error.status, 403);
+        assert_eq!(error.code, "Authorization_RequestDenied");
+        assert_eq!(error.request_id.as_deref(), Some("req"));
+        assert_eq!(error.client_request_id.as_deref(), Some("client"));
+        assert_eq!(
+            error.to_string(),
+            "Authorization_RequestDenied (HTTP 403): Insufficient privileges to complete the \
+             operation. [request-id: req, client-request-id: client]"
+        );
+
+        // Bodies that are not Graph errors fall back to the status
+        let error = GraphError::new(StatusCode::BAD_GATEWAY, &Value::Null);
+        assert_eq!(error.code, "Bad Gateway");
+        assert_eq!(error.to_string(), "Bad Gateway (HTTP 502)");
+    }
+
+    #[test]
+    fn exit_codes_match_readme() {
+        let network = reqwest::Client::new().get("not a url").build().unwrap_err();
+        let cases = [
+            (RevelioError::Config("invalid".to_string()), 2),
+            (RevelioError::Auth("denied".to_string()), 3),
+            (graph_error(400, None), 4),
+            (graph_error(401, None), 4),
+            (graph_error(403, None), 4),
+            (graph_error(404, None), 4),
+            (graph_error(500, None), 4),
+            (graph_error(502, None), 4),
+            (graph_error(429, Some("10")), 5),
+            (graph_error(503, None), 5),
+            (graph_error(504, None), 5),
+            (RevelioError::from(network), 6),
+            (RevelioError::Parse("invalid JSON".to_string()), 7),
+            (RevelioError::Io(std::io::ErrorKind::NotFound.into()), 8),
+            (RevelioError::Interrupted, 130),
+        ];
+        for (error, exit_code) in cases {
+            assert_eq!(error.exit_code(), exit_code, "{}", error);
+        }
+    }
+
+    #[test]
+    fn throttled_error_keeps_retry_after() {
+        assert!(matches!(
+            graph_error(429, Some(" 10 ")),
+            RevelioError::Throttled {
+                retry_after: Some(10),
+                ..
+            }
+        ));
+        assert!(matches!(
+            graph_error(504, None),
+            RevelioError::Throttled {
+                retry_after: None,
+                ..
+            }
+        ));
+    }
+}
@@ -1,18 +1,29 @@
 use crate::{
-    core::{auth, constants::{DEFAULT_SCOPES, USER_AGENTS_KEYS, DEFAULT_CLIENT_ID}},
+    core::certificate::ClientCertificate,
+    core::output::OutputFormat,
+    error::RevelioError,
+    core::constants::{
+        AUTHORITY_HOST_CHINA, AUTHORITY_HOST_GLOBAL, AUTHORITY_HOST_US_GOV, DEFAULT_CLIENT_ID,
+        DEFAULT_REDIRECT_URI, DEFAULT_SCOPES, FOCI_CLIENT_IDS_KEYS, GRAPH_HOST_CHINA,
+        GRAPH_HOST_GLOBAL, GRAPH_HOST_US_GOV, GRAPH_HOST_US_GOV_DOD, USER_AGENTS_KEYS,
+    },
     msgraph_api::ApiVersion,
 };
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{ops::RangeInclusive, path::PathBuf};
+use std::{
+    io::Write,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
 
 // Function to save JSON response to a file in pretty format
 pub fn save_json_to_file(
     output_dir: &str,
     file_name: &str,
     json_value: &Value,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), RevelioError> {
     // Create a PathBuf for the output directory
     let mut output_path = PathBuf::from(output_dir);
 
@@ -27,6 +38,53 @@ pub fn save_json_to_file(
     Ok(())
 }
 
+// Function to save JSON holding secrets (e.g. tokens) to a file readable only
+// by the current user
+pub fn save_private_json_to_file(
+    output_dir: &str,
+    file_name: &str,
+    json_value: &Value,
+) -> Result<(), RevelioError> {
+    let output_path = PathBuf::from(output_dir).join(file_name);
+    write_private_file(&output_path, serde_json::to_string_pretty(json_value)?.as_bytes())?;
+    eprintln!("JSON response saved to: {:?}", output_path);
+    Ok(())
+}
+
+// Write a file holding secrets (e.g. tokens), readable only by the current
+// user. The content is written to a new temporary file that replaces the
+// target, so it is never exposed with wider permissions.
+pub fn write_private_file(path: &Path, contents: &[u8]) -> Result<(), RevelioError> {
+    let temp_path = path.with_extension("tmp");
+    // A leftover temporary file would keep its permissions
+    let _ = std::fs::remove_file(&temp_path);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+// Create a directory and its parents, accessible only by the current user
+pub fn create_private_dir(path: &Path) -> Result<(), RevelioError> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(path)?;
+    Ok(())
+}
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
@@ -37,6 +95,15 @@ pub struct Cli {
     /// Custom client secret to use for API requests
     #[clap(short = 's', long)]
     pub client_secret: Option<String>,
+    /// Certificate (PEM or PFX) to use for client credentials flow
+    #[clap(long, conflicts_with = "client_secret")]
+    pub certificate: Option<String>,
+    /// PEM private key of the certificate (if not bundled with it)
+    #[clap(long, requires = "certificate")]
+    pub private_key: Option<String>,
+    /// Password of the PFX certificate or encrypted private key
+    #[clap(long, requires = "certificate")]
+    pub certificate_password: Option<String>,
     /// Tenant ID to use for API requests (e.g. organizations, common, consumers,
     /// tenant ID or domain)
     #[clap(short = 't', long, default_value = "organizations")]
@@ -50,12 +117,32 @@ pub struct Cli {
     /// Set access token to use for API requests
     #[clap(short = 'k', long, env = "REVELIO_TOKEN")]
     pub access_token: Option<String>,
+    /// Token cache file (defaults to ~/.revelio/token_cache.json)
+    #[clap(long, env = "REVELIO_TOKEN_CACHE")]
+    pub token_cache: Option<String>,
+    /// Do not read or write the token cache
+    #[clap(long)]
+    pub no_cache: bool,
     /// Username to use for password authentication flow
     #[clap(short = 'u', long)]
     pub username: Option<String>,
     /// Password to use for password authentication flow
     #[clap(short = 'p', long)]
     pub password: Option<String>,
+    /// Redirect URI to use for authorization code flow
+    #[clap(short = 'r', long, default_value = DEFAULT_REDIRECT_URI)]
+    pub redirect_uri: String,
+    /// Cloud to authenticate against and query
+    #[clap(value_enum, long, default_value_t = Cloud::Global)]
+    pub cloud: Cloud,
+    /// Custom authority host, overriding the one of the selected cloud
+    /// (e.g. https://login.microsoftonline.com)
+    #[clap(long, value_parser = parse_host)]
+    pub authority_host: Option<String>,
+    /// Custom Microsoft Graph host, overriding the one of the selected cloud
+    /// (e.g. http://localhost:8080)
+    #[clap(long, value_parser = parse_host)]
+    pub graph_host: Option<String>,
     /// API version to use for API requests
     #[clap(short = 'v', long, default_value = "v1")]
     pub api_version: ApiVersion,
@@ -68,9 +155,20 @@ pub struct Cli {
     /// Ignore SSL certificate verification
     #[clap(short = 'i', long)]
     pub ignore_ssl: bool,
+    /// Maximum number of retries of throttled requests (HTTP 429, 503, 504)
+    #[clap(long, default_value = "5")]
+    pub max_retries: u32,
+    /// Longest delay (in seconds) requested by a throttled response to wait
+    /// before retrying; longer delays fail the request
+    #[clap(long, default_value = "300")]
+    pub max_retry_after: u64,
     /// Output directory (only used when retrieving large amounts of data)
     #[clap(short = 'o', long, default_value = ".")]
     pub out_dir: String,
+    /// Output format of retrieved resources (tables are printed to the
+    /// terminal instead of being saved)
+    #[clap(value_enum, short = 'F', long, default_value_t = OutputFormat::Json)]
+    pub format: OutputFormat,
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -88,10 +186,91 @@ pub enum AuthFlow {
     Password,
 }
 
+/// Enum for national clouds
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Cloud {
+    /// Azure global cloud
+    Global,
+    /// Azure US Government (GCC High)
+    UsGov,
+    /// Azure US Government (DoD)
+    UsGovDod,
+    /// Azure China operated by 21Vianet
+    China,
+}
+
+impl Cloud {
+    /// Get the authority host of the cloud
+    pub fn authority_host(&self) -> &'static str {
+        match self {
+            Cloud::Global => AUTHORITY_HOST_GLOBAL,
+            Cloud::UsGov | Cloud::UsGovDod => AUTHORITY_HOST_US_GOV,
+            Cloud::China => AUTHORITY_HOST_CHINA,
+        }
+    }
+
+    /// Get the Microsoft Graph host of the cloud
+    pub fn graph_host(&self) -> &'static str {
+        match self {
+            Cloud::Global => GRAPH_HOST_GLOBAL,
+            Cloud::UsGov => GRAPH_HOST_US_GOV,
+            Cloud::UsGovDod => GRAPH_HOST_US_GOV_DOD,
+            Cloud::China => GRAPH_HOST_CHINA,
+        }
+    }
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// Get resources in a tenant
     Get(GetArgs),
+    /// Work with tokens
+    Token(TokenArgs),
+    /// Look up the tenant of a domain without authenticating
+    Recon(ReconArgs),
+}
+
+#[derive(Args)]
+pub struct ReconArgs {
+    /// Domain to look up (e.g. contoso.com)
+    pub domain: String,
+}
+
+#[derive(Args)]
+pub struct TokenArgs {
+    #[command(subcommand)]
+    pub command: TokenCommands,
+}
+
+#[derive(Subcommand)]
+pub enum TokenCommands {
+    /// Redeem a FOCI refresh token for other first-party client IDs
+    Exchange(ExchangeArgs),
+    /// Decode an access or ID token (without verification)
+    Inspect(InspectArgs),
+}
+
+#[derive(Args)]
+pub struct InspectArgs {
+    /// Token to decode (defaults to the access token option)
+    pub token: Option<String>,
+    /// Print the decoded token as JSON
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct ExchangeArgs {
+    /// Refresh token obtained for a family-of-client-IDs (FOCI) app
+    #[clap(long, env = "REVELIO_REFRESH_TOKEN")]
+    pub refresh_token: String,
+    /// Comma-separated list of FOCI clients to try (all clients by default)
+    #[clap(long, value_delimiter = ',', value_parser = FOCI_CLIENT_IDS_KEYS)]
+    pub clients: Vec<String>,
+    /// Comma-separated list of resources to request tokens for (Microsoft
+    /// Graph of the selected cloud by default)
+    #[clap(long, value_delimiter = ',')]
+    pub resources: Vec<String>,
 }
 
 #[derive(Args)]
@@ -105,14 +284,49 @@ pub struct GetArgs {
     /// Set skiptoken to continue from a previous request
     #[clap(long)]
     pub skiptoken: Option<String>,
+    /// Custom filter query parameter (e.g. "accountEnabled eq false")
+    #[clap(long)]
+    pub filter: Option<String>,
+    /// Custom search query parameter (e.g. "displayName:admin")
+    #[clap(long)]
+    pub search: Option<String>,
+    /// Custom orderby query parameter (e.g. "displayName desc")
+    #[clap(long)]
+    pub orderby: Option<String>,
+    /// Custom expand query parameter (related resources to include)
+    #[clap(long)]
+    pub expand: Option<String>,
+    /// Include the total number of items in the response
+    #[clap(long)]
+    pub count: bool,
     /// Maximum number of pages to return (0 for all pages)
     #[clap(long, value_parser = clap::value_parser!(u16).range(0..), default_value = "0")]
     pub pages: u16,
+    /// Continue an interrupted collection run from its checkpoint file,
+    /// appending to the same output file (in its original format)
+    #[clap(long, conflicts_with = "resource")]
+    pub resume: Option<PathBuf>,
+    /// ID of the object whose relationships to get (required by
+    /// group-members and group-owners)
+    #[clap(long, required_if_eq_any = [
+        ("resource", "group-members"),
+        ("resource", "group-owners"),
+    ])]
+    pub id: Option<String>,
+    /// Include members of nested groups (group-members)
+    #[clap(long)]
+    pub transitive: bool,
+    /// Number of users whose authentication methods are requested at the
+    /// same time (authentication-methods)
+    #[clap(long, value_parser = clap::value_parser!(u16).range(1..), default_value = "8")]
+    pub concurrency: u16,
     /// Resource to get
-    pub resource: Resource,
+    #[clap(required_unless_present = "resume")]
+    pub resource: Option<Resource>,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Resource {
     /// Get the profile of the current user
     Me,
@@ -120,13 +334,53 @@ pub enum Resource {
     Users,
     /// Get the number of users in the tenant
     UsersCount,
+    /// Get the users with their authentication methods and MFA registration details
+    AuthenticationMethods,
+    /// Get the list of groups in the tenant
+    Groups,
+    /// Get the members of a group (--id), optionally transitive
+    GroupMembers,
+    /// Get the owners of a group (--id)
+    GroupOwners,
+    /// Get the app registrations with their credentials and owners
+    Applications,
+    /// Get the service principals (enterprise apps) with their credentials and owners
+    ServicePrincipals,
+    /// Get the activated directory roles with their members
+    DirectoryRoles,
+    /// Get the directory role definitions
+    RoleDefinitions,
+    /// Get the active directory role assignments
+    RoleAssignments,
+    /// Get the directory role eligibilities (PIM)
+    RoleEligibilitySchedules,
+    /// Get the delegated permissions consented to apps
+    Oauth2PermissionGrants,
+    /// Get the app roles and application permissions assigned on service principals
+    AppRoleAssignments,
+    /// Get the devices with their registered owners and users
+    Devices,
+    /// Get the organization details and save a tenant summary (with
+    /// domains and licenses)
+    Organization,
+    /// Get the domains of the tenant
+    Domains,
+    /// Get the Conditional Access policies with resolved conditions and gaps
+    ConditionalAccessPolicies,
+    /// Get the named locations of Conditional Access
+    NamedLocations,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientConfig {
     pub client_id: String,
     pub client_secret: String,
+    pub certificate: Option<ClientCertificate>,
     pub tenant_id: String,
+    pub cloud: Cloud,
+    pub authority_host: String,
+    /// Microsoft Graph host tokens are requested for
+    pub graph_host: String,
     pub auth_flow: AuthFlow,
     pub access_token: Option<String>,
     pub scopes: String,
@@ -146,7 +400,11 @@ impl ClientConfig {
         Self {
             client_id,
             client_secret,
+            certificate: None,
             tenant_id,
+            cloud: Cloud::Global,
+            authority_host: AUTHORITY_HOST_GLOBAL.to_string(),
+            graph_host: GRAPH_HOST_GLOBAL.to_string(),
             auth_flow,
             access_token,
             scopes,
@@ -155,10 +413,17 @@ impl ClientConfig {
     }
 }
 
+#[derive(Default)]
 pub struct QueryConfig {
     pub select: Option<String>,
     pub skiptoken: Option<String>,
     pub top: u16,
+    pub filter: Option<String>,
+    pub search: Option<String>,
+    pub orderby: Option<String>,
+    pub expand: Option<String>,
+    /// Request the total number of items ($count=true)
+    pub count: bool,
 }
 
 impl QueryConfig {
@@ -167,10 +432,22 @@ impl QueryConfig {
             select,
             skiptoken,
             top,
+            ..Default::default()
         }
     }
 }
 
+/// Parse a host, defaulting to HTTPS if no scheme is given
+fn parse_host(s: &str) -> Result<String, String> {
+    let host = if s.contains("://") {
+        s.to_string()
+    } else {
+        format!("https://{}", s)
+    };
+    reqwest::Url::parse(&host).map_err(|e| format!("`{s}` isn't a valid host: {e}"))?;
+    Ok(host.trim_end_matches('/').to_string())
+}
+
 const TOP_RANGE: RangeInclusive<usize> = 1..=999;
 
 fn top_in_range(s: &str) -> Result<u16, String> {
@@ -1,16 +1,47 @@
 use chrono::Utc;
 use clap::Parser;
-use revelio::core::auth::{authenticate_credential_device, authenticate_credential_password};
-use revelio::core::constants::DEFAULT_CLIENT_ID;
-use revelio::helpers::{save_json_to_file, Cli, ClientConfig, Commands, QueryConfig, Resource};
-use revelio::msgraph_api::{create_api_client, ApiClient};
-use std::error::Error;
-use tokio;
+use colored::Colorize;
+use revelio::core::auth::{
+    authenticate_credential_client, authenticate_credential_code, authenticate_credential_device,
+    authenticate_credential_password, authenticate_refresh_token, exchange_foci_refresh_token,
+    inspect_token, requested_scopes, AuthResult,
+};
+use revelio::core::cache::{default_cache_path, TokenCache};
+use revelio::core::certificate::ClientCertificate;
+use revelio::core::checkpoint::{Checkpoint, FetchedResource};
+use revelio::core::output::{render_items, CollectionWriter, OutputFormat};
+use revelio::core::recon::recon_domain;
+use revelio::core::constants::{DEFAULT_CLIENT_ID, FOCI_CLIENT_IDS, FOCI_CLIENT_IDS_KEYS};
+use revelio::error::RevelioError;
+use revelio::helpers::{
+    save_json_to_file, save_private_json_to_file, Cli, ClientConfig, Commands, QueryConfig,
+    Resource, TokenCommands,
+};
+use revelio::msgraph_api::applications::ApplicationsApi;
+use revelio::msgraph_api::client::RetryPolicy;
+use revelio::msgraph_api::conditional_access::ConditionalAccessApi;
+use revelio::msgraph_api::devices::DevicesApi;
+use revelio::msgraph_api::groups::GroupsApi;
+use revelio::msgraph_api::me::MeApi;
+use revelio::msgraph_api::permissions::PermissionsApi;
+use revelio::msgraph_api::roles::RolesApi;
+use revelio::msgraph_api::service_principals::ServicePrincipalsApi;
+use revelio::msgraph_api::tenant::TenantApi;
+use revelio::msgraph_api::users::UsersApi;
+use revelio::msgraph_api::{create_api_client, default_select, ApiClient};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    let cli = Cli::parse();
+async fn main() {
+    if let Err(e) = run(Cli::parse()).await {
+        eprintln!("{} {}", "Error:".red(), e);
+        std::process::exit(e.exit_code());
+    }
+}
 
+async fn run(cli: Cli) -> Result<(), RevelioError> {
     let mut config = ClientConfig::new(
         cli.client_id
             .unwrap_or_else(|| DEFAULT_CLIENT_ID.to_string()),
@@ -21,8 +52,154 @@ async fn main() -> Result<(), Box<dyn Error>> {
         cli.scopes,
         cli.user_agent,
     );
+    config.cloud = cli.cloud;
+    config.authority_host = cli
+        .authority_host
+        .unwrap_or_else(|| cli.cloud.authority_host().to_string());
+    config.graph_host = cli
+        .graph_host
+        .unwrap_or_else(|| cli.cloud.graph_host().to_string());
+    config.certificate = cli.certificate.map(|path| {
+        ClientCertificate::new(path, cli.private_key, cli.certificate_password)
+    });
+
+    // Token commands work with the given tokens and do not require authentication
+    if let Commands::Token(args) = &cli.command {
+        match &args.command {
+            TokenCommands::Exchange(exchange_args) => {
+                let client_names: Vec<&str> = if exchange_args.clients.is_empty() {
+                    FOCI_CLIENT_IDS_KEYS.to_vec()
+                } else {
+                    exchange_args.clients.iter().map(|name| name.as_str()).collect()
+                };
+                let clients: Vec<(&str, &str)> = client_names
+                    .into_iter()
+                    .map(|name| (name, FOCI_CLIENT_IDS[name]))
+                    .collect();
+                let resources = if exchange_args.resources.is_empty() {
+                    vec![config.graph_host.clone()]
+                } else {
+                    exchange_args.resources.clone()
+                };
+                let results = exchange_foci_refresh_token(
+                    &config,
+                    &exchange_args.refresh_token,
+                    &clients,
+                    &resources,
+                )
+                .await;
+                for result in &results {
+                    if result.success {
+                        eprintln!(
+                            "{} {} ({}) {}: {}",
+                            "[+]".green(),
+                            result.client_name,
+                            result.client_id,
+                            result.resource,
+                            result.scopes.join(" ")
+                        );
+                    } else {
+                        eprintln!(
+                            "{} {} ({}) {}: {}",
+                            "[-]".red(),
+                            result.client_name,
+                            result.client_id,
+                            result.resource,
+                            result.error.as_deref().unwrap_or_default()
+                        );
+                    }
+                }
+                // Save results to a file only readable by the current user, as it holds tokens
+                let file_name = format!("{}_token_exchange.json", Utc::now().format("%Y%m%d%H%M%S"));
+                save_private_json_to_file(&cli.out_dir, &file_name, &serde_json::to_value(&results)?)?;
+            }
+            TokenCommands::Inspect(inspect_args) => {
+                let token = inspect_args
+                    .token
+                    .as_ref()
+                    .or(cli.access_token.as_ref())
+                    .ok_or_else(|| RevelioError::Config("a token is required to inspect".to_string()))?;
+                let info = inspect_token(token)?;
+                if inspect_args.json {
+                    println!("{}", serde_json::to_string_pretty(&info)?);
+                } else {
+                    println!("{}", info);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    // Recon only queries public endpoints of the authority host
+    if let Commands::Recon(args) = &cli.command {
+        let result = recon_domain(&config, &args.domain, cli.proxy.as_deref(), cli.ignore_ssl).await?;
+        let result = serde_json::to_value(&result)?;
+        println!("{}", serde_json::to_string_pretty(&result)?);
+        let file_name = format!(
+            "{}_recon_{}.json",
+            Utc::now().format("%Y%m%d%H%M%S"),
+            args.domain
+        );
+        save_json_to_file(&cli.out_dir, &file_name, &result)?;
+        return Ok(());
+    }
+
+    // Load the token cache unless an access token is provided or caching is disabled
+    let scopes = requested_scopes(&config);
+    let account = cli.username.clone();
+    let mut token_cache = match (&config.access_token, cli.no_cache) {
+        (None, false) => Some(TokenCache::load(
+            &cli.token_cache
+                .as_ref()
+                .map(PathBuf::from)
+                .unwrap_or_else(default_cache_path),
+        )),
+        _ => None,
+    };
+
+    // Latest tokens, used by the API client to refresh the access token
+    let mut auth_state: Option<AuthResult> = None;
+
+    // Reuse a cached access token, refreshing it if it has expired
+    if let Some(cache) = &mut token_cache {
+        if let Some(entry) = cache
+            .find(
+                &config.tenant_id,
+                &config.client_id,
+                &config.authority_host,
+                &config.graph_host,
+                &scopes,
+                account.as_deref(),
+            )
+            .cloned()
+        {
+            if entry.is_valid() {
+                eprintln!("Using cached access token for {}", entry.account.green());
+                config.access_token = Some(entry.access_token.clone());
+                let mut auth_result = AuthResult::new(config.clone(), config.access_token.clone());
+                auth_result.refresh_token = entry.refresh_token.clone();
+                auth_result.expires_at = Some(entry.expires_at);
+                auth_state = Some(auth_result);
+            } else if let Some(refresh_token) = &entry.refresh_token {
+                eprintln!("Refreshing cached access token for {}", entry.account.green());
+                match authenticate_refresh_token(&config, refresh_token).await {
+                    Ok(auth_result) => {
+                        cache.insert(&auth_result, &scopes, Some(&entry.account));
+                        config.access_token = auth_result.auth_token.clone();
+                        auth_state = Some(auth_result);
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to refresh cached access token: {}", e);
+                        cache.remove(&entry);
+                    }
+                }
+            } else {
+                cache.remove(&entry);
+            }
+        }
+    }
 
-    // Authenticate user if no access token is provided
+    // Authenticate user if no access token is provided or cached
     match &config.access_token {
         Some(_) => {}
         None => {
@@ -32,102 +209,334 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 }
                 revelio::helpers::AuthFlow::Password => {
                     // If username and password are not provided, throw an error
-                    if cli.username.is_none() || cli.password.is_none() {
-                        eprintln!("Username and password are required for password authentication flow");
-                        std::process::exit(1);
-                    } else {
-                        authenticate_credential_password(&config, cli.username.unwrap(), cli.password.unwrap())
-                            .await
+                    match (cli.username, cli.password) {
+                        (Some(username), Some(password)) => {
+                            authenticate_credential_password(&config, username, password).await
+                        }
+                        _ => Err(RevelioError::Config(
+                            "username and password are required for password authentication flow"
+                                .to_string(),
+                        )),
                     }
                 },
                 revelio::helpers::AuthFlow::Client => {
-                    unimplemented!();
+                    // If neither client secret nor certificate is provided, throw an error
+                    if config.client_secret.is_empty() && config.certificate.is_none() {
+                        return Err(RevelioError::Config(
+                            "client secret or certificate is required for client credentials flow"
+                                .to_string(),
+                        ));
+                    }
+                    authenticate_credential_client(&config).await
                 },
                 revelio::helpers::AuthFlow::Code => {
-                    unimplemented!();
+                    authenticate_credential_code(&config, cli.redirect_uri).await
                 },
-            };
+            }?;
+            if let Some(cache) = &mut token_cache {
+                cache.insert(&auth_result, &scopes, account.as_deref());
+            }
             config.access_token = auth_result.auth_token.to_owned();
+            auth_state = Some(auth_result);
         }
     }
 
     // Exit if authentication failed
-    if config.access_token.is_none() {
-        eprintln!("Authentication failed");
-        std::process::exit(1);
-    } else {
-        eprintln!("Authentication successful");
-        // Save the access token to a file
-        if let Some(token) = &config.access_token {
-            // Do not save if token was provided via command line or
-            // environment variable
-            if cli.access_token.is_none() {
-                std::fs::write("access_token.txt", token)?;
-                eprintln!("Access token saved to access_token.txt");
-            }
+    let Some(access_token) = config.access_token.clone() else {
+        return Err(RevelioError::Auth("no access token was obtained".to_string()));
+    };
+    eprintln!("Authentication successful");
+    // Show which scopes and roles were actually granted
+    if let Ok(info) = inspect_token(&access_token) {
+        eprintln!("{}", info);
+    }
+    // Save the tokens to the cache (skipped if token was provided via
+    // command line or environment variable)
+    if let Some(cache) = &token_cache {
+        match cache.save() {
+            Ok(_) => eprintln!("Token cache saved to {:?}", cache.path()),
+            Err(e) => eprintln!("Failed to save token cache: {}", e),
         }
     }
 
     let api_client: Box<dyn ApiClient> = create_api_client(
         cli.api_version,
-        config.access_token.clone().unwrap(),
+        access_token,
+        auth_state,
+        config.graph_host.clone(),
         cli.proxy,
         cli.ignore_ssl,
-    );
+        RetryPolicy {
+            max_retries: cli.max_retries,
+            max_retry_after: Duration::from_secs(cli.max_retry_after),
+        },
+    )?;
 
-    match cli.command {
+    let result = match cli.command {
         Commands::Get(args) => {
-            let query_config = QueryConfig::new(args.select, args.skiptoken, args.top);
-            match args.resource {
-                Resource::Me => {
-                    match api_client
-                        .get_me(Some(api_client.query_config_to_params(&query_config)))
-                        .await
-                    {
-                        Ok(result) => {
-                            println!("{}", serde_json::to_string_pretty(&result).unwrap());
-                        }
-                        Err(e) => {
-                            eprintln!("Error: {}", e);
-                        }
-                    }
-                }
-                Resource::Users => {
-                    match api_client
-                        .get_users(
-                            Some(api_client.query_config_to_params(&query_config)),
+            let mut query_config = QueryConfig::new(args.select, args.skiptoken, args.top);
+            query_config.filter = args.filter;
+            query_config.search = args.search;
+            query_config.orderby = args.orderby;
+            query_config.expand = args.expand;
+            query_config.count = args.count;
+            // A resumed run fetches the resource of its checkpoint again, with
+            // the same query, so that the remaining pages are processed like
+            // the first ones
+            let mut resumed = args.resume.as_deref().map(resumed_checkpoint).transpose()?;
+            let query: Vec<(String, String)> = resumed
+                .as_ref()
+                .map(|checkpoint| checkpoint.query.clone())
+                .unwrap_or_default();
+            let params: Vec<(&str, String)> = match &resumed {
+                Some(_) => query.iter().map(|(key, value)| (key.as_str(), value.clone())).collect(),
+                None => api_client.query_config_to_params(&query_config),
+            };
+            let fetched = match &resumed {
+                Some(checkpoint) => checkpoint.fetched.clone(),
+                None => args.resource.map(|resource| FetchedResource {
+                    resource,
+                    id: args.id,
+                    transitive: args.transitive,
+                }),
+            };
+            let object_id = fetched
+                .as_ref()
+                .and_then(|fetched| fetched.id.as_deref())
+                .unwrap_or_default();
+            let transitive = fetched.as_ref().is_some_and(|fetched| fetched.transitive);
+            let resource_select = fetched
+                .as_ref()
+                .and_then(|fetched| default_select(fetched.resource));
+            // Stream a collection to a file named after it
+            macro_rules! collection {
+                ($name:expr, $writer:ident => $fetch:expr) => {
+                    collection!($name, true, $writer => $fetch)
+                };
+                ($name:expr, $resumable:expr, $writer:ident => $fetch:expr) => {{
+                    let $writer = match resumed.take() {
+                        Some(checkpoint) => CollectionWriter::resume(checkpoint).await?,
+                        None => collection_writer(
+                            &cli.out_dir,
+                            $name,
+                            cli.format,
+                            query_config.select.clone(),
+                            &params,
+                            fetched.clone().filter(|_| $resumable),
+                        ),
+                    };
+                    let mut $writer = $writer.with_default_select(resource_select);
+                    let result = until_interrupted($fetch).await;
+                    finish_collection($writer, result).await
+                }};
+            }
+            match fetched.as_ref().map(|fetched| fetched.resource) {
+                Some(Resource::Me) => api_client
+                    .get_me(Some(params))
+                    .await
+                    .and_then(|result| {
+                        print_item(&result, cli.format, query_config.select.as_deref())
+                    }),
+                Some(Resource::Users) => collection!("users", writer => api_client
+                    .get_users(Some(params.clone()), args.pages, &mut writer)),
+                Some(Resource::AuthenticationMethods) => {
+                    collection!("authentication_methods", writer => api_client
+                        .get_authentication_methods(
+                            Some(params.clone()),
                             args.pages,
-                        )
-                        .await
-                    {
-                        Ok(result) => {
-                            // Save results to file
-                            let file_name =
-                                format!("{}_users.json", Utc::now().format("%Y%m%d%H%M%S"));
-                            save_json_to_file(&cli.out_dir, &file_name, &result)
-                                .expect("Failed to save JSON response to file");
-                        }
-                        Err(e) => {
-                            eprintln!("Error: {}", e);
-                        }
-                    }
+                            args.concurrency.into(),
+                            &mut writer,
+                        ))
                 }
-                Resource::UsersCount => {
-                    match api_client
-                        .get_users_count(Some(api_client.query_config_to_params(&query_config)))
-                        .await
-                    {
-                        Ok(result) => {
-                            println!("{}", result);
-                        }
-                        Err(e) => {
-                            eprintln!("Error: {}", e);
-                        }
-                    }
+                Some(Resource::Groups) => collection!("groups", writer => api_client
+                    .get_groups(Some(params.clone()), args.pages, &mut writer)),
+                Some(Resource::GroupMembers) => {
+                    collection!("group_members", writer => api_client.get_group_members(
+                        object_id,
+                        transitive,
+                        Some(params.clone()),
+                        args.pages,
+                        &mut writer,
+                    ))
+                }
+                Some(Resource::GroupOwners) => {
+                    collection!("group_owners", writer => api_client.get_group_owners(
+                        object_id,
+                        Some(params.clone()),
+                        args.pages,
+                        &mut writer,
+                    ))
+                }
+                Some(Resource::Applications) => {
+                    collection!("applications", writer => api_client
+                        .get_applications(Some(params.clone()), args.pages, &mut writer))
+                }
+                Some(Resource::ServicePrincipals) => {
+                    collection!("service_principals", writer => api_client
+                        .get_service_principals(Some(params.clone()), args.pages, &mut writer))
+                }
+                Some(Resource::DirectoryRoles) => {
+                    collection!("directory_roles", writer => api_client
+                        .get_directory_roles(Some(params.clone()), args.pages, &mut writer))
+                }
+                Some(Resource::RoleDefinitions) => {
+                    collection!("role_definitions", writer => api_client
+                        .get_role_definitions(Some(params.clone()), args.pages, &mut writer))
                 }
+                Some(Resource::RoleAssignments) => {
+                    collection!("role_assignments", writer => api_client
+                        .get_role_assignments(Some(params.clone()), args.pages, &mut writer))
+                }
+                Some(Resource::RoleEligibilitySchedules) => {
+                    collection!("role_eligibility_schedules", writer => api_client
+                        .get_role_eligibility_schedules(Some(params.clone()), args.pages, &mut writer))
+                }
+                Some(Resource::Oauth2PermissionGrants) => {
+                    collection!("oauth2_permission_grants", writer => api_client
+                        .get_oauth2_permission_grants(Some(params.clone()), args.pages, &mut writer))
+                }
+                // Spans one collection per service principal, so it cannot be resumed
+                Some(Resource::AppRoleAssignments) => {
+                    collection!("app_role_assignments", false, writer => api_client
+                        .get_app_role_assignments(Some(params.clone()), args.pages, &mut writer))
+                }
+                Some(Resource::Devices) => collection!("devices", writer => api_client
+                    .get_devices(Some(params.clone()), args.pages, &mut writer)),
+                Some(Resource::Organization) => api_client
+                    .get_tenant_summary(Some(params))
+                    .await
+                    .and_then(|summary| {
+                        print_item(&summary["organization"], cli.format, query_config.select.as_deref())?;
+                        // Save results to file
+                        let file_name =
+                            format!("{}_tenant_summary.json", Utc::now().format("%Y%m%d%H%M%S"));
+                        save_json_to_file(&cli.out_dir, &file_name, &summary)
+                    }),
+                Some(Resource::Domains) => collection!("domains", writer => api_client
+                    .get_domains(Some(params.clone()), args.pages, &mut writer)),
+                // Written at once after resolving the policies, so it cannot be resumed
+                Some(Resource::ConditionalAccessPolicies) => {
+                    collection!("conditional_access_policies", false, writer => api_client
+                        .get_conditional_access_policies(Some(params.clone()), &mut writer))
+                }
+                Some(Resource::NamedLocations) => {
+                    collection!("named_locations", writer => api_client
+                        .get_named_locations(Some(params.clone()), args.pages, &mut writer))
+                }
+                Some(Resource::UsersCount) => api_client
+                    .get_users_count(Some(params))
+                    .await
+                    .map(|result| println!("{}", result)),
+                // Enforced by clap
+                None => Err(RevelioError::Config("a resource is required".to_string())),
+            }
+        }
+        // Handled before authentication
+        Commands::Token(_) | Commands::Recon(_) => Ok(()),
+    };
+
+    // Persist tokens refreshed while running the command
+    if let (Some(cache), Some(auth_result)) = (&mut token_cache, api_client.get_auth_result()) {
+        if auth_result.auth_token != config.access_token {
+            cache.insert(&auth_result, &scopes, account.as_deref());
+            if let Err(e) = cache.save() {
+                eprintln!("Failed to save token cache: {}", e);
             }
         }
     }
 
+    result
+}
+
+/// Create a writer for a collection saved under the output directory, along
+/// with a checkpoint recording the progress of the run if the fetched
+/// resource is given (i.e. the run is resumable)
+fn collection_writer(
+    out_dir: &str,
+    name: &str,
+    format: OutputFormat,
+    select: Option<String>,
+    query: &[(&str, String)],
+    fetched: Option<FetchedResource>,
+) -> CollectionWriter {
+    let prefix = format!("{}_{}", Utc::now().format("%Y%m%d%H%M%S"), name);
+    let output_file = PathBuf::from(out_dir).join(format!("{}.{}", prefix, format.extension()));
+    let writer = CollectionWriter::new(output_file.clone(), format, select);
+    // Tables are only printed, so there is nothing to resume
+    let Some(fetched) = fetched.filter(|_| format != OutputFormat::Table) else {
+        return writer;
+    };
+    let checkpoint = Checkpoint::new(
+        PathBuf::from(out_dir).join(format!("{}.checkpoint.json", prefix)),
+        name,
+        query,
+        output_file.clone(),
+        format,
+    )
+    .with_fetched(fetched);
+    writer.with_checkpoint(checkpoint)
+}
+
+/// Load the checkpoint of an interrupted collection run to resume
+fn resumed_checkpoint(checkpoint_path: &Path) -> Result<Checkpoint, RevelioError> {
+    let checkpoint = Checkpoint::load(checkpoint_path)?;
+    if checkpoint.is_complete() {
+        return Err(RevelioError::Config(format!(
+            "{} collection in {:?} is already complete",
+            checkpoint.resource, checkpoint_path
+        )));
+    }
+    if checkpoint.fetched.is_none() {
+        return Err(RevelioError::Config(format!(
+            "{:?} does not record the resource of the run, so it cannot be resumed",
+            checkpoint_path
+        )));
+    }
+    eprintln!(
+        "Resuming {} collection after {} items",
+        checkpoint.resource.green(),
+        checkpoint.item_count
+    );
+    Ok(checkpoint)
+}
+
+/// Run a fetch until it completes or the user presses Ctrl-C
+async fn until_interrupted<T>(
+    fetch: impl std::future::Future<Output = Result<T, RevelioError>>,
+) -> Result<T, RevelioError> {
+    tokio::select! {
+        result = fetch => result,
+        _ = tokio::signal::ctrl_c() => {
+            eprintln!("\n{}", "Interrupted, keeping the results fetched so far".yellow());
+            Err(RevelioError::Interrupted)
+        }
+    }
+}
+
+/// Terminate the output of a collection and report where it was saved
+async fn finish_collection(
+    writer: CollectionWriter,
+    result: Result<usize, RevelioError>,
+) -> Result<(), RevelioError> {
+    let count = writer.count();
+    let checkpoint_path = writer
+        .checkpoint()
+        .filter(|checkpoint| !checkpoint.is_complete())
+        .map(|checkpoint| checkpoint.path().to_path_buf());
+    if let Some(path) = writer.finish().await? {
+        eprintln!("{} items saved to: {:?}", count, path);
+    }
+    if let Some(path) = checkpoint_path {
+        eprintln!("Continue this run with: --resume {}", path.display().to_string().blue());
+    }
+    result.map(|_| ())
+}
+
+/// Print a single resource in the given format
+fn print_item(item: &Value, format: OutputFormat, select: Option<&str>) -> Result<(), RevelioError> {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(item)?),
+        format => print!("{}", render_items(std::slice::from_ref(item), format, select)?),
+    }
     Ok(())
 }
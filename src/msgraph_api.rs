@@ -1,9 +1,37 @@
+pub mod applications;
+pub mod beta;
+pub mod client;
+pub mod conditional_access;
+pub mod devices;
+pub mod groups;
 pub mod me;
+pub mod permissions;
+pub mod roles;
+pub mod service_principals;
+pub mod tenant;
 pub mod users;
 pub mod v1;
+use async_trait::async_trait;
 use clap::ValueEnum;
+use colored::Colorize;
+use futures::stream::{self, StreamExt};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::{RequestBuilder, Response, StatusCode, Url};
+use serde_json::Value;
 
-use crate::helpers::QueryConfig;
+use crate::core::auth::{authenticate_refresh_token, AuthResult};
+use crate::core::output::CollectionSink;
+use crate::error::{Result, RevelioError};
+use crate::helpers::{QueryConfig, Resource};
+
+// Header enabling advanced query capabilities on directory objects
+const CONSISTENCY_LEVEL: &str = "ConsistencyLevel";
+
+// Number of objects whose relationships are requested at the same time
+const RELATIONSHIPS_CONCURRENCY: usize = 8;
+
+/// Properties selected for related directory objects (e.g. owners)
+pub(crate) const RELATED_OBJECT_SELECT: &str = "id,displayName,userPrincipalName";
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum ApiVersion {
@@ -11,25 +39,541 @@ pub enum ApiVersion {
     Beta,
 }
 
-pub trait ApiClient: me::MeApi + users::UsersApi {
+#[async_trait(?Send)]
+pub trait ApiClient {
+    /// Get the connection state shared by all API versions
+    fn get_base_client(&self) -> &client::BaseClient;
     /// Get the access token
-    fn get_token(&self) -> &str;
+    fn get_token(&self) -> String {
+        self.get_base_client().token()
+    }
+    /// Obtain a new access token using the stored refresh token, returning
+    /// whether the access token was replaced
+    async fn refresh_access_token(&self) -> bool {
+        let Some(auth_result) = self.get_auth_result() else {
+            return false;
+        };
+        let Some(refresh_token) = &auth_result.refresh_token else {
+            return false;
+        };
+        eprintln!("Access token has expired, refreshing...");
+        match authenticate_refresh_token(&auth_result.credential, refresh_token).await {
+            Ok(refreshed) => {
+                self.get_base_client().set_auth_result(refreshed);
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to refresh access token: {}", e);
+                false
+            }
+        }
+    }
+    /// Get the latest authentication result, including refreshed tokens
+    fn get_auth_result(&self) -> Option<AuthResult> {
+        self.get_base_client().auth_result()
+    }
+    /// Build the headers authorizing requests with the current access token
+    fn auth_headers(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        let value = HeaderValue::from_str(&format!("Bearer {}", self.get_token()))
+            .map_err(|_| RevelioError::Auth("access token contains invalid characters".to_string()))?;
+        headers.insert(AUTHORIZATION, value);
+        Ok(headers)
+    }
+    /// Send a request, authorizing it with the current access token.
+    ///
+    /// Throttled requests (HTTP 429, 503 and 504) are retried after the delay
+    /// given by the Retry-After header or an exponential backoff with jitter,
+    /// up to the configured retry budget. Requests rejected because the access
+    /// token expired are retried once after refreshing it. Advanced queries
+    /// are sent with the `ConsistencyLevel: eventual` header they require.
+    async fn send_request(&self, request: RequestBuilder) -> Result<Response> {
+        let retry_policy = self.get_base_client().retry_policy();
+        let mut retries: u32 = 0;
+        let mut refreshed = false;
+        loop {
+            let mut request = request
+                .try_clone()
+                .ok_or_else(|| RevelioError::Config("request cannot be retried".to_string()))?
+                .headers(self.auth_headers()?)
+                .build()?;
+            if client::requires_eventual_consistency(request.url()) {
+                request
+                    .headers_mut()
+                    .insert(CONSISTENCY_LEVEL, HeaderValue::from_static("eventual"));
+            }
+            let response = self.get_client().execute(request).await?;
+            let status = response.status();
+            if status == StatusCode::UNAUTHORIZED && !refreshed && self.refresh_access_token().await {
+                refreshed = true;
+                continue;
+            }
+            if !client::is_retryable_status(status) || retries >= retry_policy.max_retries {
+                return Ok(response);
+            }
+            let delay = match client::retry_after(response.headers()) {
+                // Fail rather than wait longer than allowed
+                Some(delay) if delay > retry_policy.max_retry_after => {
+                    eprintln!(
+                        "\n{}",
+                        format!(
+                            "Request throttled (HTTP {}) for {}s, more than --max-retry-after ({}s)",
+                            status.as_u16(),
+                            delay.as_secs(),
+                            retry_policy.max_retry_after.as_secs()
+                        )
+                        .yellow()
+                    );
+                    let headers = response.headers().clone();
+                    let body = response.json::<Value>().await.unwrap_or(Value::Null);
+                    return Err(RevelioError::from_response(status, &headers, &body));
+                }
+                Some(delay) => delay,
+                None => client::backoff_delay(retries),
+            };
+            retries += 1;
+            // Break the progress line of collections
+            eprintln!(
+                "\n{}",
+                format!(
+                    "Request throttled (HTTP {}), retrying in {:.1}s ({}/{})",
+                    status.as_u16(),
+                    delay.as_secs_f64(),
+                    retries,
+                    retry_policy.max_retries
+                )
+                .yellow()
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+    /// Check whether the access token is accepted by the API
+    async fn check_access_token_validity(&self) -> Result<bool> {
+        let response = self
+            .send_request(
+                self.get_client()
+                    .get(format!("{}/me", self.get_base_path())),
+            )
+            .await?;
+
+        // App-only tokens are valid even though they cannot query /me
+        Ok(response.status() != StatusCode::UNAUTHORIZED)
+    }
+    /// Ensure the access token is valid (refreshing it if needed)
+    async fn ensure_valid_token(&self) -> Result<()> {
+        if !self.check_access_token_validity().await? {
+            return Err(RevelioError::Auth("access token is not valid".to_string()));
+        }
+        Ok(())
+    }
     /// Get the reqwest client
-    fn get_client(&self) -> &reqwest::Client;
+    fn get_client(&self) -> &reqwest::Client {
+        self.get_base_client().client()
+    }
     /// Get the base path
-    fn get_base_path(&self) -> &str;
+    fn get_base_path(&self) -> &str {
+        self.get_base_client().base_path()
+    }
     /// Convert QueryConfig into reqwest params format
-    fn query_config_to_params(&self, query_config: &QueryConfig) -> Vec<(&str, String)>;
+    fn query_config_to_params(&self, query_config: &QueryConfig) -> Vec<(&str, String)> {
+        let mut query_vec: Vec<(&str, String)> = Vec::new();
+        if let Some(select) = &query_config.select {
+            query_vec.push(("$select", select.clone()));
+        }
+        if let Some(filter) = &query_config.filter {
+            query_vec.push(("$filter", filter.clone()));
+        }
+        if let Some(search) = &query_config.search {
+            // Search clauses must be enclosed in double quotes
+            if search.contains('"') {
+                query_vec.push(("$search", search.clone()));
+            } else {
+                query_vec.push(("$search", format!("\"{}\"", search)));
+            }
+        }
+        if let Some(orderby) = &query_config.orderby {
+            query_vec.push(("$orderby", orderby.clone()));
+        }
+        if let Some(expand) = &query_config.expand {
+            query_vec.push(("$expand", expand.clone()));
+        }
+        if query_config.count {
+            query_vec.push(("$count", "true".to_string()));
+        }
+        if let Some(skiptoken) = &query_config.skiptoken {
+            query_vec.push(("$skiptoken", skiptoken.clone()));
+        }
+        query_vec.push(("$top", query_config.top.to_string()));
+        query_vec
+    }
+    /// Get a single resource (or the first page of a collection)
+    async fn get_json(&self, path: &str, params: Vec<(&str, String)>) -> Result<Value> {
+        let url = format!("{}{}", self.get_base_path(), path);
+        let response = self
+            .send_request(self.get_client().get(url).query(&params))
+            .await?;
+        parse_graph_response(response).await
+    }
+    /// Fetch every page of a collection at once, without checking the access
+    /// token or reporting progress. Meant for the relationships of every item
+    /// of another collection, which `$expand` would cap at 20 objects.
+    async fn get_all_pages(&self, path: &str, params: Vec<(&str, String)>) -> Result<Vec<Value>> {
+        let mut url = Url::parse(&format!("{}{}", self.get_base_path(), path))?;
+        if !params.is_empty() {
+            url.query_pairs_mut().extend_pairs(params);
+        }
+        let mut items: Vec<Value> = Vec::new();
+        loop {
+            let response = self.send_request(self.get_client().get(url)).await?;
+            let mut body = parse_graph_response(response).await?;
+            if let Value::Array(page) = body["value"].take() {
+                items.extend(page);
+            }
+            let Some(next_link) = body["@odata.nextLink"].as_str() else {
+                break;
+            };
+            url = Url::parse(next_link)?;
+        }
+        Ok(items)
+    }
+    /// Fetch all items of a collection, following `@odata.nextLink` for up to
+    /// `pages` pages (all pages if 0)
+    async fn get_collection(
+        &self,
+        path: &str,
+        params: Vec<(&str, String)>,
+        pages: u16,
+    ) -> Result<Vec<Value>> {
+        let mut items: Vec<Value> = Vec::new();
+        self.stream_collection(path, params, pages, &mut items).await?;
+        Ok(items)
+    }
+    /// Fetch a collection page by page, writing the items of each page to
+    /// `sink` as they arrive, and return the number of items fetched. If the
+    /// sink resumes an interrupted run, fetching continues at its next page.
+    ///
+    /// If a page fails, the latest skiptoken is reported and the error is
+    /// returned; the items already written to `sink` are kept.
+    async fn stream_collection(
+        &self,
+        path: &str,
+        params: Vec<(&str, String)>,
+        pages: u16,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize> {
+        self.ensure_valid_token().await?;
+        match sink.resume_link() {
+            Some(next_link) => self.resume_pages(path, params, &next_link, pages, sink).await,
+            None => self.stream_pages(path, params, pages, sink).await,
+        }
+    }
+    /// Fetch a collection like `stream_collection`, without checking the
+    /// access token first. Meant for collections fetched once the token was
+    /// checked (e.g. one collection per item of another one).
+    async fn stream_pages(
+        &self,
+        path: &str,
+        params: Vec<(&str, String)>,
+        pages: u16,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize> {
+        let mut url = Url::parse(&format!("{}{}", self.get_base_path(), path))?;
+        url.query_pairs_mut().extend_pairs(params);
+        // Keep the consistency level of the first page for the next ones
+        let eventual = client::requires_eventual_consistency(&url);
+        self.stream_collection_from(url, eventual, pages, sink).await
+    }
+    /// Continue fetching a collection from the `@odata.nextLink` of an
+    /// interrupted run, given the path and query parameters of that run
+    async fn resume_pages(
+        &self,
+        path: &str,
+        params: Vec<(&str, String)>,
+        next_link: &str,
+        pages: u16,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize> {
+        // Never send the access token anywhere but the configured API
+        if !next_link.starts_with(&format!("{}/", self.get_base_path())) {
+            return Err(RevelioError::Config(format!(
+                "next link {} does not belong to {}",
+                next_link,
+                self.get_base_path()
+            )));
+        }
+        // Next links may not repeat the query requiring eventual consistency
+        let mut original = Url::parse(&format!("{}{}", self.get_base_path(), path))?;
+        original.query_pairs_mut().extend_pairs(params);
+        let eventual = client::requires_eventual_consistency(&original);
+        self.stream_collection_from(Url::parse(next_link)?, eventual, pages, sink)
+            .await
+    }
+    /// Fetch a collection page by page starting at the given URL, sending
+    /// every request with eventual consistency if `eventual` is set. The
+    /// access token is expected to have been checked by the caller.
+    async fn stream_collection_from(
+        &self,
+        mut url: Url,
+        eventual: bool,
+        pages: u16,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize> {
+        let path = url.path().to_string();
+        let mut total: Option<u64> = None;
+        let mut item_count: usize = 0;
+        let mut page_count: u16 = 0;
+        let mut skiptoken = String::new();
+        loop {
+            let mut request = self.get_client().get(url.clone());
+            if eventual {
+                request = request.header(CONSISTENCY_LEVEL, "eventual");
+            }
+            let response = self.send_request(request).await?;
+            let body = match parse_graph_response(response).await {
+                Ok(body) => body,
+                // The caller keeps the items fetched so far and reports the error
+                Err(e) => {
+                    if page_count > 0 {
+                        eprintln!();
+                    }
+                    if !skiptoken.is_empty() {
+                        eprintln!("Latest skiptoken: {}", skiptoken.blue());
+                    }
+                    return Err(e);
+                }
+            };
+
+            let Value::Array(items) = &body["value"] else {
+                break;
+            };
+            if items.is_empty() {
+                break;
+            }
+            let next_link = body["@odata.nextLink"].as_str();
+            sink.write_page(items, next_link).await?;
+            item_count += items.len();
+            page_count += 1;
+            // Only the first page carries the total count requested with $count
+            if let Some(count) = body["@odata.count"].as_u64() {
+                total = Some(count);
+            }
+            match total {
+                Some(total) => eprint!(
+                    "\rFetched {} of {} items from {} ({} pages)",
+                    item_count, total, path, page_count
+                ),
+                None => eprint!(
+                    "\rFetched {} items from {} ({} pages)",
+                    item_count, path, page_count
+                ),
+            }
+            if pages > 0 && page_count >= pages {
+                break;
+            }
+
+            let Some(next_link) = next_link else {
+                break;
+            };
+            url = Url::parse(next_link)?;
+            if let Some((_, value)) = url.query_pairs().find(|(key, _)| key == "$skiptoken") {
+                skiptoken = value.to_string();
+            }
+        }
+        if page_count > 0 {
+            eprintln!();
+        }
+        Ok(item_count)
+    }
+}
+
+/// Add the default `$select` to the query parameters unless one was given
+pub(crate) fn with_default_select<'a>(
+    mut params: Vec<(&'a str, String)>,
+    select: &str,
+) -> Vec<(&'a str, String)> {
+    if !params.iter().any(|(key, _)| *key == "$select") {
+        params.push(("$select", select.to_owned()));
+    }
+    params
+}
+
+/// Add the default `$expand` to the query parameters unless one was given
+pub(crate) fn with_default_expand<'a>(
+    mut params: Vec<(&'a str, String)>,
+    expand: &str,
+) -> Vec<(&'a str, String)> {
+    if !params.iter().any(|(key, _)| *key == "$expand") {
+        params.push(("$expand", expand.to_owned()));
+    }
+    params
+}
+
+/// Properties selected by default for the collection of a resource, if any
+pub fn default_select(resource: Resource) -> Option<&'static str> {
+    match resource {
+        Resource::Users => Some(users::DEFAULT_USER_SELECT),
+        Resource::AuthenticationMethods => Some(users::DEFAULT_AUTHENTICATION_USER_SELECT),
+        Resource::Groups => Some(groups::DEFAULT_GROUP_SELECT),
+        Resource::Applications => Some(applications::DEFAULT_APPLICATION_SELECT),
+        Resource::ServicePrincipals => Some(service_principals::DEFAULT_SERVICE_PRINCIPAL_SELECT),
+        Resource::DirectoryRoles => Some(roles::DEFAULT_DIRECTORY_ROLE_SELECT),
+        Resource::RoleDefinitions => Some(roles::DEFAULT_ROLE_DEFINITION_SELECT),
+        Resource::Devices => Some(devices::DEFAULT_DEVICE_SELECT),
+        _ => None,
+    }
 }
 
 pub fn create_api_client(
     api_version: ApiVersion,
     token: String,
+    auth_result: Option<AuthResult>,
+    graph_host: String,
     proxy: Option<String>,
     nossl: bool,
-) -> Box<dyn ApiClient> {
-    match api_version {
-        ApiVersion::V1 => Box::new(v1::client::ApiClientV1::new(token, proxy, nossl)),
-        ApiVersion::Beta => unimplemented!("Beta API not implemented yet"),
+    retry_policy: client::RetryPolicy,
+) -> Result<Box<dyn ApiClient>> {
+    Ok(match api_version {
+        ApiVersion::V1 => Box::new(v1::client::ApiClientV1::new(
+            token,
+            auth_result,
+            graph_host,
+            proxy,
+            nossl,
+            retry_policy,
+        )?),
+        ApiVersion::Beta => Box::new(beta::client::ApiClientBeta::new(
+            token,
+            auth_result,
+            graph_host,
+            proxy,
+            nossl,
+            retry_policy,
+        )?),
+    })
+}
+
+/// Read the JSON body of a Graph response, turning error statuses into
+/// errors even when their body is not JSON
+pub(crate) async fn parse_graph_response(response: Response) -> Result<Value> {
+    let status = response.status();
+    let headers = response.headers().clone();
+    match response.json::<Value>().await {
+        Ok(body) if status.is_success() => Ok(body),
+        Ok(body) => Err(RevelioError::from_response(status, &headers, &body)),
+        Err(e) if status.is_success() => Err(e.into()),
+        Err(_) => Err(RevelioError::from_response(status, &headers, &Value::Null)),
+    }
+}
+
+/// Build the path of a relationship of a directory object (e.g.
+/// `/groups/{id}/members`), rejecting IDs that would alter the URL
+pub(crate) fn object_path(collection: &str, id: &str, relationship: &str) -> Result<String> {
+    let valid = !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.@".contains(c));
+    if !valid {
+        return Err(RevelioError::Config(format!("invalid object ID `{}`", id)));
+    }
+    Ok(format!("/{}/{}/{}", collection, id, relationship))
+}
+
+/// Expose the type of a directory object (from `@odata.type`) as a regular
+/// `objectType` property, so it is kept in CSV and table output
+pub(crate) fn with_object_type(mut item: Value) -> Value {
+    let object_type = item["@odata.type"]
+        .as_str()
+        .map(|odata_type| odata_type.trim_start_matches("#microsoft.graph.").to_string());
+    if let (Some(object_type), Some(obj)) = (object_type, item.as_object_mut()) {
+        obj.insert("objectType".to_string(), Value::String(object_type));
+    }
+    item
+}
+
+/// Sink adding relationships of every directory object of a collection
+/// (e.g. the owners of applications) before passing them on. Relationships
+/// are paged in full, as `$expand` returns at most 20 related objects.
+pub(crate) struct RelationshipsSink<'a, C: ?Sized> {
+    client: &'a C,
+    collection: &'static str,
+    relationships: &'static [&'static str],
+    select: Option<&'static str>,
+    inner: &'a mut dyn CollectionSink,
+}
+
+impl<'a, C: ApiClient + ?Sized> RelationshipsSink<'a, C> {
+    /// Add the given relationships of the objects of `collection`, selecting
+    /// `select` on the related objects (all default properties if `None`)
+    pub(crate) fn new(
+        client: &'a C,
+        collection: &'static str,
+        relationships: &'static [&'static str],
+        select: Option<&'static str>,
+        inner: &'a mut dyn CollectionSink,
+    ) -> Self {
+        Self {
+            client,
+            collection,
+            relationships,
+            select,
+            inner,
+        }
+    }
+
+    async fn with_relationships(&self, mut object: Value) -> Value {
+        for relationship in self.relationships {
+            let related = match self.related_objects(&object, relationship).await {
+                Ok(related) => Value::Array(related),
+                Err(e) => {
+                    eprintln!(
+                        "Failed to get {} of {}: {}",
+                        relationship,
+                        object_name(&object).unwrap_or_default(),
+                        e
+                    );
+                    Value::Null
+                }
+            };
+            if let Some(obj) = object.as_object_mut() {
+                obj.insert(relationship.to_string(), related);
+            }
+        }
+        object
+    }
+
+    async fn related_objects(&self, object: &Value, relationship: &str) -> Result<Vec<Value>> {
+        let id = object["id"]
+            .as_str()
+            .ok_or_else(|| RevelioError::Parse("directory object without ID".to_string()))?;
+        let path = object_path(self.collection, id, relationship)?;
+        let params = self
+            .select
+            .map(|select| vec![("$select", select.to_string())])
+            .unwrap_or_default();
+        self.client.get_all_pages(&path, params).await
     }
 }
+
+#[async_trait(?Send)]
+impl<C: ApiClient + ?Sized> CollectionSink for RelationshipsSink<'_, C> {
+    async fn write_page(&mut self, items: &[Value], next_link: Option<&str>) -> Result<()> {
+        let objects: Vec<Value> = stream::iter(items.iter().cloned())
+            .map(|object| self.with_relationships(object))
+            .buffered(RELATIONSHIPS_CONCURRENCY)
+            .collect()
+            .await;
+        self.inner.write_page(&objects, next_link).await
+    }
+
+    fn resume_link(&mut self) -> Option<String> {
+        self.inner.resume_link()
+    }
+}
+
+/// Name of a directory object: its UPN, display name or app ID
+pub(crate) fn object_name(object: &Value) -> Option<&str> {
+    object["userPrincipalName"]
+        .as_str()
+        .or(object["displayName"].as_str())
+        .or(object["appId"].as_str())
+}
@@ -0,0 +1,103 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+use super::{with_default_select, ApiClient, RelationshipsSink, RELATED_OBJECT_SELECT};
+use crate::core::output::{CollectionSink, MapSink};
+use crate::error::RevelioError;
+
+/// Properties selected for applications when no `$select` is given
+pub const DEFAULT_APPLICATION_SELECT: &str = "id,appId,displayName,signInAudience,\
+    publisherDomain,createdDateTime,identifierUris,passwordCredentials,keyCredentials,\
+    requiredResourceAccess,web,spa,publicClient,appRoles";
+
+#[async_trait(?Send)]
+pub trait ApplicationsApi: ApiClient {
+    /// Get the app registrations of the tenant with their credentials
+    /// metadata and owners, writing them to `sink` page by page and
+    /// returning the number of applications fetched
+    async fn get_applications(
+        &self,
+        params: Option<Vec<(&str, String)>>,
+        pages: u16,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize, RevelioError> {
+        let params = with_default_select(params.unwrap_or_default(), DEFAULT_APPLICATION_SELECT);
+        let mut sink = MapSink::new(sink, |application| {
+            with_credentials_summary(with_application_reply_urls(application))
+        });
+        let mut sink = RelationshipsSink::new(
+            self,
+            "applications",
+            &["owners"],
+            Some(RELATED_OBJECT_SELECT),
+            &mut sink,
+        );
+        self.stream_collection("/applications", params, pages, &mut sink)
+            .await
+    }
+}
+
+impl<C: ApiClient + ?Sized> ApplicationsApi for C {}
+
+/// Gather the redirect URIs of every platform (web, SPA and public client)
+/// of an application into a `replyUrls` property, as on service principals
+fn with_application_reply_urls(mut application: Value) -> Value {
+    let reply_urls: Vec<Value> = ["web", "spa", "publicClient"]
+        .iter()
+        .filter_map(|platform| application[platform]["redirectUris"].as_array())
+        .flatten()
+        .cloned()
+        .collect();
+    if let Some(obj) = application.as_object_mut() {
+        obj.insert("replyUrls".to_string(), Value::Array(reply_urls));
+    }
+    application
+}
+
+/// Summarize the secrets and certificates of an application or service
+/// principal into a `credentials` property (type, name, hint, expiry) and
+/// flag those having any (`password-credentials`, `key-credentials`),
+/// expired ones (`expired-credentials`) or no owners (`no-owners`)
+pub(crate) fn with_credentials_summary(mut object: Value) -> Value {
+    let now = Utc::now();
+    let mut credentials: Vec<Value> = Vec::new();
+    let mut flags: Vec<Value> = Vec::new();
+    for (property, credential_type, flag) in [
+        ("passwordCredentials", "password", "password-credentials"),
+        ("keyCredentials", "key", "key-credentials"),
+    ] {
+        let Some(entries) = object[property].as_array().filter(|entries| !entries.is_empty())
+        else {
+            continue;
+        };
+        flags.push(flag.into());
+        for entry in entries {
+            let expired = entry["endDateTime"]
+                .as_str()
+                .and_then(|end| DateTime::parse_from_rfc3339(end).ok())
+                .map(|end| end < now);
+            credentials.push(json!({
+                "type": credential_type,
+                "keyId": entry["keyId"],
+                "displayName": entry["displayName"],
+                "hint": entry["hint"],
+                "usage": entry["usage"],
+                "startDateTime": entry["startDateTime"],
+                "endDateTime": entry["endDateTime"],
+                "expired": expired,
+            }));
+        }
+    }
+    if credentials.iter().any(|credential| credential["expired"] == true) {
+        flags.push("expired-credentials".into());
+    }
+    if object["owners"].as_array().is_some_and(|owners| owners.is_empty()) {
+        flags.push("no-owners".into());
+    }
+    if let Some(obj) = object.as_object_mut() {
+        obj.insert("credentials".to_string(), Value::Array(credentials));
+        obj.insert("flags".to_string(), Value::Array(flags));
+    }
+    object
+}
@@ -0,0 +1,34 @@
+use crate::{
+    core::auth::AuthResult,
+    error::Result,
+    msgraph_api::{
+        client::{BaseClient, RetryPolicy},
+        ApiClient,
+    },
+};
+
+pub struct ApiClientBeta {
+    base: BaseClient,
+}
+
+impl ApiClientBeta {
+    pub fn new(
+        token: String,
+        auth_result: Option<AuthResult>,
+        graph_host: String,
+        proxy: Option<String>,
+        nossl: bool,
+        retry_policy: RetryPolicy,
+    ) -> Result<Self> {
+        let base_path = format!("{}/beta", graph_host);
+        Ok(ApiClientBeta {
+            base: BaseClient::new(token, auth_result, base_path, proxy, nossl, retry_policy)?,
+        })
+    }
+}
+
+impl ApiClient for ApiClientBeta {
+    fn get_base_client(&self) -> &BaseClient {
+        &self.base
+    }
+}
@@ -0,0 +1,266 @@
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{StatusCode, Url};
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::core::auth::AuthResult;
+use crate::error::{Result, RevelioError};
+
+/// Connection state shared by the API clients of every Graph version.
+pub struct BaseClient {
+    client: reqwest::Client,
+    base_path: String,
+    token: RwLock<String>,
+    auth_result: RwLock<Option<AuthResult>>,
+    retry_policy: RetryPolicy,
+}
+
+/// Limits on retrying throttled requests
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Maximum number of retries of a throttled request
+    pub max_retries: u32,
+    /// Longest delay requested by the Retry-After header to wait before
+    /// retrying; throttled requests asking for more fail instead
+    pub max_retry_after: Duration,
+}
+
+// Base and maximum delays of the exponential backoff
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+impl BaseClient {
+    pub fn new(
+        token: String,
+        auth_result: Option<AuthResult>,
+        base_path: String,
+        proxy: Option<String>,
+        nossl: bool,
+        retry_policy: RetryPolicy,
+    ) -> Result<Self> {
+        let mut builder = reqwest::Client::builder().danger_accept_invalid_certs(nossl);
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(
+                reqwest::Proxy::all(proxy)
+                    .map_err(|e| RevelioError::Config(format!("invalid proxy: {}", e)))?,
+            );
+        }
+        let client = builder.build()?;
+        Ok(BaseClient {
+            client,
+            base_path,
+            token: RwLock::new(token),
+            auth_result: RwLock::new(auth_result),
+            retry_policy,
+        })
+    }
+
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    pub fn base_path(&self) -> &str {
+        &self.base_path
+    }
+
+    /// Limits on retrying throttled requests
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry_policy
+    }
+
+    pub fn token(&self) -> String {
+        self.token.read().unwrap().clone()
+    }
+
+    pub fn auth_result(&self) -> Option<AuthResult> {
+        self.auth_result.read().unwrap().clone()
+    }
+
+    /// Replace the access token with the one of the given authentication result
+    pub fn set_auth_result(&self, auth_result: AuthResult) {
+        if let Some(token) = &auth_result.auth_token {
+            *self.token.write().unwrap() = token.clone();
+        }
+        *self.auth_result.write().unwrap() = Some(auth_result);
+    }
+}
+
+/// Check whether a request uses advanced query capabilities, which Microsoft
+/// Graph only supports with the `ConsistencyLevel: eventual` header
+/// ($search, $count, some $filter operators, and $filter with $orderby)
+pub fn requires_eventual_consistency(url: &Url) -> bool {
+    if url.path().ends_with("/$count") {
+        return true;
+    }
+    let mut has_filter = false;
+    let mut has_orderby = false;
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "$search" => return true,
+            "$count" if value == "true" => return true,
+            "$filter" => {
+                let filter = value.to_lowercase();
+                if [" ne ", "not(", "not (", "endswith(", "/$count"]
+                    .iter()
+                    .any(|operator| filter.contains(operator))
+                {
+                    return true;
+                }
+                has_filter = true;
+            }
+            "$orderby" => has_orderby = true,
+            _ => {}
+        }
+    }
+    has_filter && has_orderby
+}
+
+/// Check whether a request failed with a transient status worth retrying
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Get the delay requested by the Retry-After header (in seconds or as an HTTP
+/// date)
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let delay = match value.parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => {
+            let date = DateTime::parse_from_rfc2822(value).ok()?;
+            (date.with_timezone(&Utc) - Utc::now()).to_std().ok()?
+        }
+    };
+    Some(delay)
+}
+
+/// Get the exponential backoff delay of the given retry, with random jitter
+pub fn backoff_delay(retry: u32) -> Duration {
+    let delay = BACKOFF_BASE
+        .saturating_mul(2u32.saturating_pow(retry))
+        .min(BACKOFF_MAX);
+    // Wait between half and the full delay to spread concurrent retries
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.subsec_nanos())
+        .unwrap_or_default();
+    delay / 2 + delay.mul_f64(f64::from(nanos % 1000) / 2000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        headers
+    }
+
+    #[test]
+    fn retry_after_parses_seconds() {
+        assert_eq!(retry_after(&headers("7")), Some(Duration::from_secs(7)));
+        assert_eq!(retry_after(&headers(" 0 ")), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn retry_after_parses_http_date() {
+        let date = (Utc::now() + chrono::Duration::seconds(30)).to_rfc2822();
+        let delay = retry_after(&headers(&date)).unwrap();
+        assert!(delay > Duration::from_secs(25) && delay <= Duration::from_secs(30));
+    }
+
+    #[test]
+    fn retry_after_keeps_large_values() {
+        assert_eq!(
+            retry_after(&headers("86400")),
+            Some(Duration::from_secs(86400))
+        );
+        let date = (Utc::now() + chrono::Duration::days(1)).to_rfc2822();
+        assert!(retry_after(&headers(&date)).unwrap() > Duration::from_secs(86000));
+    }
+
+    #[test]
+    fn retry_after_ignores_invalid_values() {
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("soon")), None);
+        assert_eq!(retry_after(&headers("-5")), None);
+        // Dates in the past give no delay to wait for
+        let date = (Utc::now() - chrono::Duration::seconds(30)).to_rfc2822();
+        assert_eq!(retry_after(&headers(&date)), None);
+    }
+
+    #[test]
+    fn backoff_delay_grows_within_bounds() {
+        for retry in 0..8 {
+            let full = BACKOFF_BASE.saturating_mul(1 << retry).min(BACKOFF_MAX);
+            let delay = backoff_delay(retry);
+            assert!(delay >= full / 2 && delay <= full, "retry {}: {:?}", retry, delay);
+        }
+    }
+
+    #[test]
+    fn backoff_delay_is_capped() {
+        for retry in [10, 31, 32, 100, u32::MAX] {
+            let delay = backoff_delay(retry);
+            assert!(delay >= BACKOFF_MAX / 2 && delay <= BACKOFF_MAX);
+        }
+    }
+
+    fn eventual(url: &str) -> bool {
+        requires_eventual_consistency(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn eventual_consistency_for_advanced_queries() {
+        let base = "https://graph.microsoft.com/v1.0";
+        assert!(eventual(&format!("{}/users/$count", base)));
+        assert!(eventual(&format!(
+            "{}/users?$search=%22displayName:admin%22",
+            base
+        )));
+        assert!(eventual(&format!("{}/users?$count=true", base)));
+        assert!(eventual(&format!(
+            "{}/users?$filter=accountEnabled ne true",
+            base
+        )));
+        assert!(eventual(&format!(
+            "{}/users?$filter=NOT(accountEnabled eq true)",
+            base
+        )));
+        assert!(eventual(&format!(
+            "{}/users?$filter=endsWith(mail,'@contoso.com')",
+            base
+        )));
+        assert!(eventual(&format!(
+            "{}/groups?$filter=members/$count eq 0",
+            base
+        )));
+        assert!(eventual(&format!(
+            "{}/users?$filter=accountEnabled eq true&$orderby=displayName",
+            base
+        )));
+    }
+
+    #[test]
+    fn no_eventual_consistency_for_basic_queries() {
+        let base = "https://graph.microsoft.com/v1.0";
+        assert!(!eventual(&format!("{}/users", base)));
+        assert!(!eventual(&format!("{}/users?$count=false&$top=10", base)));
+        assert!(!eventual(&format!(
+            "{}/users?$filter=accountEnabled eq true",
+            base
+        )));
+        assert!(!eventual(&format!("{}/users?$orderby=displayName", base)));
+        // Operators are only looked for in $filter
+        assert!(!eventual(&format!(
+            "{}/users?$select=id&$skiptoken=not(x)",
+            base
+        )));
+    }
+}
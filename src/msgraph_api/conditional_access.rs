@@ -0,0 +1,489 @@
+use async_trait::async_trait;
+use colored::Colorize;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeSet, HashMap};
+
+use super::roles::role_names;
+use super::{parse_graph_response, ApiClient};
+use crate::core::output::{CollectionSink, MapSink};
+use crate::error::RevelioError;
+
+// Maximum number of IDs resolved by a single getByIds request
+const GET_BY_IDS_LIMIT: usize = 1000;
+
+// Client app types of legacy authentication protocols
+const LEGACY_CLIENT_APP_TYPES: [&str; 2] = ["exchangeActiveSync", "other"];
+
+// Platforms conditions can be restricted to
+const DEVICE_PLATFORMS: [&str; 6] = [
+    "android",
+    "iOS",
+    "windows",
+    "windowsPhone",
+    "macOS",
+    "linux",
+];
+
+#[async_trait(?Send)]
+pub trait ConditionalAccessApi: ApiClient {
+    /// Get the Conditional Access policies with the users, groups, roles,
+    /// applications and locations of their conditions resolved to names and
+    /// their gaps highlighted, returning the number of policies
+    async fn get_conditional_access_policies(
+        &self,
+        params: Option<Vec<(&str, String)>>,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize, RevelioError> {
+        // Policies are few, so they are resolved all at once
+        let policies = self
+            .get_collection(
+                "/identity/conditionalAccess/policies",
+                params.unwrap_or_default(),
+                0,
+            )
+            .await?;
+        let names = resolve_names(self, &policies).await;
+        let policies: Vec<Value> = policies
+            .into_iter()
+            .map(|policy| with_policy_gaps(with_resolved_conditions(policy, &names)))
+            .collect();
+        for gap in tenant_gaps(&policies) {
+            eprintln!("{} {}", "[!]".yellow(), gap);
+        }
+        sink.write_page(&policies, None).await?;
+        Ok(policies.len())
+    }
+    /// Get the named locations used by Conditional Access policies
+    async fn get_named_locations(
+        &self,
+        params: Option<Vec<(&str, String)>>,
+        pages: u16,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize, RevelioError> {
+        let mut sink = MapSink::new(sink, with_location_ranges);
+        self.stream_collection(
+            "/identity/conditionalAccess/namedLocations",
+            params.unwrap_or_default(),
+            pages,
+            &mut sink,
+        )
+        .await
+    }
+}
+
+impl<C: ApiClient + ?Sized> ConditionalAccessApi for C {}
+
+/// Resolve the IDs referenced by the conditions of the policies to names.
+/// IDs that cannot be resolved (e.g. for lack of permissions) are kept.
+async fn resolve_names<C: ApiClient + ?Sized>(
+    client: &C,
+    policies: &[Value],
+) -> HashMap<String, String> {
+    let mut names: HashMap<String, String> = HashMap::new();
+    let ids = |condition: &str, properties: &[&str]| -> BTreeSet<String> {
+        policies
+            .iter()
+            .flat_map(|policy| {
+                properties
+                    .iter()
+                    .filter_map(|property| policy["conditions"][condition][property].as_array())
+                    .flatten()
+                    .filter_map(|id| id.as_str())
+                    .filter(|id| is_guid(id))
+                    .map(|id| id.to_string())
+                    .collect::<Vec<String>>()
+            })
+            .collect()
+    };
+
+    // Users and groups
+    let objects: Vec<String> = ids(
+        "users",
+        &[
+            "includeUsers",
+            "excludeUsers",
+            "includeGroups",
+            "excludeGroups",
+        ],
+    )
+    .into_iter()
+    .collect();
+    for chunk in objects.chunks(GET_BY_IDS_LIMIT) {
+        match directory_objects_by_ids(client, chunk).await {
+            Ok(objects) => names.extend(objects),
+            Err(e) => eprintln!("Failed to resolve users and groups: {}", e),
+        }
+    }
+
+    // Directory roles, referenced by template ID
+    if !ids("users", &["includeRoles", "excludeRoles"]).is_empty() {
+        match role_names(client).await {
+            Ok(roles) => names.extend(roles),
+            Err(e) => eprintln!("Failed to resolve roles: {}", e),
+        }
+    }
+
+    // Applications, referenced by app ID
+    for app_id in ids(
+        "applications",
+        &["includeApplications", "excludeApplications"],
+    ) {
+        let path = format!("/servicePrincipals(appId='{}')", app_id);
+        match client
+            .get_json(&path, vec![("$select", "displayName".to_string())])
+            .await
+        {
+            Ok(sp) => {
+                if let Some(name) = sp["displayName"].as_str() {
+                    names.insert(app_id, name.to_string());
+                }
+            }
+            Err(e) => eprintln!("Failed to resolve application {}: {}", app_id, e),
+        }
+    }
+
+    // Named locations
+    if !ids("locations", &["includeLocations", "excludeLocations"]).is_empty() {
+        match client
+            .get_collection("/identity/conditionalAccess/namedLocations", Vec::new(), 0)
+            .await
+        {
+            Ok(locations) => names.extend(locations.iter().filter_map(|location| {
+                Some((
+                    location["id"].as_str()?.to_string(),
+                    location["displayName"].as_str()?.to_string(),
+                ))
+            })),
+            Err(e) => eprintln!("Failed to resolve named locations: {}", e),
+        }
+    }
+    names
+}
+
+/// Get the names of users and groups by ID
+async fn directory_objects_by_ids<C: ApiClient + ?Sized>(
+    client: &C,
+    ids: &[String],
+) -> Result<Vec<(String, String)>, RevelioError> {
+    let url = format!("{}/directoryObjects/getByIds", client.get_base_path());
+    let response = client
+        .send_request(
+            client
+                .get_client()
+                .post(url)
+                .json(&json!({ "ids": ids, "types": ["user", "group"] })),
+        )
+        .await?;
+    let body = parse_graph_response(response).await?;
+    Ok(body["value"]
+        .as_array()
+        .map(|objects| {
+            objects
+                .iter()
+                .filter_map(|object| {
+                    let name = object["userPrincipalName"]
+                        .as_str()
+                        .or(object["displayName"].as_str())?;
+                    Some((object["id"].as_str()?.to_string(), name.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+fn is_guid(id: &str) -> bool {
+    id.len() == 36 && id.chars().all(|c| c.is_ascii_hexdigit() || c == '-')
+}
+
+/// Add a `resolved` property mirroring the users, applications and
+/// locations conditions of a policy with IDs replaced by names
+fn with_resolved_conditions(mut policy: Value, names: &HashMap<String, String>) -> Value {
+    let mut resolved = Map::new();
+    for (condition, properties) in [
+        (
+            "users",
+            &[
+                "includeUsers",
+                "excludeUsers",
+                "includeGroups",
+                "excludeGroups",
+                "includeRoles",
+                "excludeRoles",
+            ][..],
+        ),
+        (
+            "applications",
+            &["includeApplications", "excludeApplications"][..],
+        ),
+        ("locations", &["includeLocations", "excludeLocations"][..]),
+    ] {
+        for property in properties {
+            let Some(ids) = policy["conditions"][condition][property].as_array() else {
+                continue;
+            };
+            let values: Vec<Value> = ids
+                .iter()
+                .map(|id| {
+                    id.as_str()
+                        .and_then(|id| names.get(id))
+                        .map(|name| Value::from(name.as_str()))
+                        .unwrap_or_else(|| id.clone())
+                })
+                .collect();
+            resolved.insert(property.to_string(), Value::Array(values));
+        }
+    }
+    if let Some(obj) = policy.as_object_mut() {
+        obj.insert("resolved".to_string(), Value::Object(resolved));
+    }
+    policy
+}
+
+/// Add a `gaps` property listing the weaknesses of a policy: not enforced,
+/// excluded users, groups or roles, and platforms left out
+fn with_policy_gaps(mut policy: Value) -> Value {
+    let mut gaps: Vec<Value> = Vec::new();
+    match policy["state"].as_str() {
+        Some("enabled") => {}
+        Some("enabledForReportingButNotEnforced") => gaps.push("report-only, not enforced".into()),
+        Some(state) => gaps.push(format!("not enabled ({})", state).into()),
+        None => {}
+    }
+    for (property, kind) in [
+        ("excludeUsers", "users"),
+        ("excludeGroups", "groups"),
+        ("excludeRoles", "roles"),
+    ] {
+        if let Some(excluded) = policy["resolved"][property]
+            .as_array()
+            .filter(|excluded| !excluded.is_empty())
+        {
+            let excluded: Vec<&str> = excluded.iter().filter_map(|name| name.as_str()).collect();
+            gaps.push(format!("excludes {}: {}", kind, excluded.join(", ")).into());
+        }
+    }
+    let platforms = &policy["conditions"]["platforms"];
+    if let Some(included) = platforms["includePlatforms"].as_array() {
+        if !included.iter().any(|platform| platform == "all") {
+            let missing: Vec<&str> = DEVICE_PLATFORMS
+                .iter()
+                .filter(|platform| !included.iter().any(|included| included == **platform))
+                .copied()
+                .collect();
+            if !missing.is_empty() {
+                gaps.push(format!("platforms not covered: {}", missing.join(", ")).into());
+            }
+        }
+    }
+    if let Some(excluded) = platforms["excludePlatforms"]
+        .as_array()
+        .filter(|excluded| !excluded.is_empty())
+    {
+        let excluded: Vec<&str> = excluded
+            .iter()
+            .filter_map(|platform| platform.as_str())
+            .collect();
+        gaps.push(format!("platforms excluded: {}", excluded.join(", ")).into());
+    }
+    if let Some(obj) = policy.as_object_mut() {
+        obj.insert("gaps".to_string(), Value::Array(gaps));
+    }
+    policy
+}
+
+/// Find the weaknesses of the policies as a whole
+fn tenant_gaps(policies: &[Value]) -> Vec<String> {
+    let enabled: Vec<&Value> = policies
+        .iter()
+        .filter(|policy| policy["state"] == "enabled")
+        .collect();
+    let for_all_users = |policy: &Value| {
+        policy["conditions"]["users"]["includeUsers"]
+            .as_array()
+            .is_some_and(|users| users.iter().any(|user| user == "All"))
+    };
+    let grants = |policy: &Value, control: &str| {
+        policy["grantControls"]["builtInControls"]
+            .as_array()
+            .is_some_and(|controls| controls.iter().any(|c| c == control))
+    };
+
+    let mut gaps = Vec::new();
+    let blocks_legacy_auth = enabled
+        .iter()
+        .filter(|policy| for_all_users(policy))
+        .any(|policy| {
+            let client_app_types = policy["conditions"]["clientAppTypes"].as_array();
+            grants(policy, "block")
+                && LEGACY_CLIENT_APP_TYPES.iter().all(|legacy| {
+                    client_app_types
+                        .is_some_and(|types| types.iter().any(|t| t == legacy || t == "all"))
+                })
+        });
+    if !blocks_legacy_auth {
+        gaps.push("No enabled policy blocks legacy authentication for all users".to_string());
+    }
+    if !enabled
+        .iter()
+        .filter(|policy| for_all_users(policy))
+        .any(|policy| {
+            grants(policy, "mfa") || !policy["grantControls"]["authenticationStrength"].is_null()
+        })
+    {
+        gaps.push("No enabled policy requires MFA for all users".to_string());
+    }
+    gaps
+}
+
+/// Add the IP ranges of a named location as a plain list (`ranges`)
+fn with_location_ranges(mut location: Value) -> Value {
+    let ranges: Vec<Value> = location["ipRanges"]
+        .as_array()
+        .map(|ranges| {
+            ranges
+                .iter()
+                .map(|range| range["cidrAddress"].clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some(obj) = location.as_object_mut() {
+        obj.insert("ranges".to_string(), Value::Array(ranges));
+        if let Some(odata_type) = obj.get("@odata.type").and_then(|t| t.as_str()) {
+            let location_type = odata_type
+                .trim_start_matches("#microsoft.graph.")
+                .to_string();
+            obj.insert("type".to_string(), Value::String(location_type));
+        }
+    }
+    location
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gaps(policy: Value) -> Vec<Value> {
+        with_policy_gaps(policy)["gaps"].as_array().unwrap().clone()
+    }
+
+    #[test]
+    fn policy_gaps_report_state_and_exclusions() {
+        let policy = json!({
+            "state": "enabledForReportingButNotEnforced",
+            "resolved": {
+                "excludeUsers": ["breakglass@contoso.com"],
+                "excludeGroups": [],
+                "excludeRoles": ["Global Administrator", "Directory Synchronization Accounts"],
+            },
+        });
+        assert_eq!(
+            gaps(policy),
+            vec![
+                json!("report-only, not enforced"),
+                json!("excludes users: breakglass@contoso.com"),
+                json!("excludes roles: Global Administrator, Directory Synchronization Accounts"),
+            ]
+        );
+        assert_eq!(
+            gaps(json!({"state": "disabled"})),
+            vec![json!("not enabled (disabled)")]
+        );
+    }
+
+    #[test]
+    fn policy_gaps_report_platforms() {
+        let policy = json!({
+            "state": "enabled",
+            "conditions": {"platforms": {
+                "includePlatforms": ["android", "iOS", "windows", "macOS"],
+                "excludePlatforms": ["windowsPhone"],
+            }},
+        });
+        let missing: Vec<&str> = DEVICE_PLATFORMS
+            .iter()
+            .filter(|platform| !["android", "iOS", "windows", "macOS"].contains(platform))
+            .copied()
+            .collect();
+        assert_eq!(
+            gaps(policy),
+            vec![
+                json!(format!("platforms not covered: {}", missing.join(", "))),
+                json!("platforms excluded: windowsPhone"),
+            ]
+        );
+        let policy = json!({
+            "state": "enabled",
+            "conditions": {"platforms": {"includePlatforms": ["all"], "excludePlatforms": []}},
+        });
+        assert!(gaps(policy).is_empty());
+    }
+
+    fn policy(state: &str, users: &[&str], client_app_types: &[&str], controls: &[&str]) -> Value {
+        json!({
+            "state": state,
+            "conditions": {
+                "users": {"includeUsers": users},
+                "clientAppTypes": client_app_types,
+            },
+            "grantControls": {"builtInControls": controls},
+        })
+    }
+
+    #[test]
+    fn tenant_gaps_without_baseline_policies() {
+        assert_eq!(
+            tenant_gaps(&[]),
+            vec![
+                "No enabled policy blocks legacy authentication for all users",
+                "No enabled policy requires MFA for all users",
+            ]
+        );
+        // Policies that are not enabled or do not target all users do not count
+        let policies = [
+            policy(
+                "enabledForReportingButNotEnforced",
+                &["All"],
+                &["exchangeActiveSync", "other"],
+                &["block"],
+            ),
+            policy(
+                "enabled",
+                &["22222222-2222-2222-2222-222222222222"],
+                &["all"],
+                &["mfa"],
+            ),
+        ];
+        assert_eq!(tenant_gaps(&policies).len(), 2);
+    }
+
+    #[test]
+    fn tenant_gaps_with_baseline_policies() {
+        let policies = [
+            policy(
+                "enabled",
+                &["All"],
+                &["exchangeActiveSync", "other"],
+                &["block"],
+            ),
+            policy("enabled", &["All"], &["all"], &["mfa"]),
+        ];
+        assert!(tenant_gaps(&policies).is_empty());
+        // Blocking only some legacy clients leaves a gap
+        let policies = [
+            policy("enabled", &["All"], &["exchangeActiveSync"], &["block"]),
+            policy("enabled", &["All"], &["browser"], &["mfa"]),
+        ];
+        assert_eq!(
+            tenant_gaps(&policies),
+            vec!["No enabled policy blocks legacy authentication for all users"]
+        );
+        // Authentication strengths require MFA as well
+        let mut strength = policy("enabled", &["All"], &["all"], &[]);
+        strength["grantControls"]["authenticationStrength"] =
+            json!({"id": "00000000-0000-0000-0000-000000000002"});
+        assert_eq!(
+            tenant_gaps(&[strength]),
+            vec!["No enabled policy blocks legacy authentication for all users"]
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::{object_name, with_default_select, ApiClient, RelationshipsSink, RELATED_OBJECT_SELECT};
+use crate::core::output::{CollectionSink, MapSink};
+use crate::error::RevelioError;
+
+/// Properties selected for devices when no `$select` is given
+pub const DEFAULT_DEVICE_SELECT: &str = "id,deviceId,displayName,operatingSystem,\
+    operatingSystemVersion,trustType,profileType,isCompliant,isManaged,accountEnabled,\
+    manufacturer,model,approximateLastSignInDateTime,registrationDateTime,\
+    onPremisesSyncEnabled,extensionAttributes";
+
+#[async_trait(?Send)]
+pub trait DevicesApi: ApiClient {
+    /// Get the devices of the tenant with their registered owners and users,
+    /// writing them to `sink` page by page and returning the number of
+    /// devices fetched
+    async fn get_devices(
+        &self,
+        params: Option<Vec<(&str, String)>>,
+        pages: u16,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize, RevelioError> {
+        let params = with_default_select(params.unwrap_or_default(), DEFAULT_DEVICE_SELECT);
+        let mut sink = MapSink::new(sink, with_registered_names);
+        let mut sink = RelationshipsSink::new(
+            self,
+            "devices",
+            &["registeredOwners", "registeredUsers"],
+            Some(RELATED_OBJECT_SELECT),
+            &mut sink,
+        );
+        self.stream_collection("/devices", params, pages, &mut sink)
+            .await
+    }
+}
+
+impl<C: ApiClient + ?Sized> DevicesApi for C {}
+
+/// Add the names of the registered owners (`ownerNames`) and users
+/// (`userNames`) of a device
+fn with_registered_names(mut device: Value) -> Value {
+    let names = |objects: &Value| -> Vec<Value> {
+        objects
+            .as_array()
+            .map(|objects| objects.iter().filter_map(object_name).map(Value::from).collect())
+            .unwrap_or_default()
+    };
+    let owner_names = names(&device["registeredOwners"]);
+    let user_names = names(&device["registeredUsers"]);
+    if let Some(obj) = device.as_object_mut() {
+        obj.insert("ownerNames".to_string(), Value::Array(owner_names));
+        obj.insert("userNames".to_string(), Value::Array(user_names));
+    }
+    device
+}
@@ -1,11 +1,28 @@
 use async_trait::async_trait;
 use serde_json::Value;
 
+use super::users::DEFAULT_USER_SELECT;
+use super::{parse_graph_response, with_default_select, ApiClient};
+use crate::error::RevelioError;
+
 #[async_trait(?Send)]
-pub trait MeApi {
+pub trait MeApi: ApiClient {
     /// Get profile of the current user
-    async fn get_me(
-        &self,
-        params: Option<Vec<(&str, String)>>,
-    ) -> Result<Value, Box<dyn std::error::Error>>;
+    async fn get_me(&self, params: Option<Vec<(&str, String)>>) -> Result<Value, RevelioError> {
+        self.ensure_valid_token().await?;
+        let params = with_default_select(params.unwrap_or_default(), DEFAULT_USER_SELECT);
+        let url = format!("{}/me", self.get_base_path());
+        let response = self
+            .send_request(self.get_client().get(url.as_str()).query(&params))
+            .await?;
+        let mut result = parse_graph_response(response).await?;
+        // Convert Value into a mutable Map
+        if let Some(obj) = result.as_object_mut() {
+            // Remove the "age" property
+            obj.remove("@odata.context");
+        }
+        Ok(result)
+    }
 }
+
+impl<C: ApiClient + ?Sized> MeApi for C {}
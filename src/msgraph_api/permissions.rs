@@ -0,0 +1,343 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::{object_path, ApiClient};
+use crate::core::constants::DANGEROUS_PERMISSIONS;
+use crate::core::output::{CollectionSink, MapSink};
+use crate::error::RevelioError;
+
+// App role ID of assignments granting access to an app without a specific role
+const DEFAULT_APP_ROLE_ID: &str = "00000000-0000-0000-0000-000000000000";
+
+#[async_trait(?Send)]
+pub trait PermissionsApi: ApiClient {
+    /// Get the delegated permissions consented to apps, resolving client and
+    /// resource apps and flagging dangerous permissions
+    async fn get_oauth2_permission_grants(
+        &self,
+        params: Option<Vec<(&str, String)>>,
+        pages: u16,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize, RevelioError> {
+        let service_principals = service_principals(self).await?;
+        let mut sink = MapSink::new(sink, |grant| {
+            with_resolved_grant(grant, &service_principals)
+        });
+        self.stream_collection(
+            "/oauth2PermissionGrants",
+            params.unwrap_or_default(),
+            pages,
+            &mut sink,
+        )
+        .await
+    }
+    /// Get the app roles (application permissions included) assigned on every
+    /// service principal exposing app roles, resolving role IDs to names and
+    /// flagging dangerous permissions
+    async fn get_app_role_assignments(
+        &self,
+        params: Option<Vec<(&str, String)>>,
+        pages: u16,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize, RevelioError> {
+        // Fetching the service principals checks the access token, so the
+        // assignments of each of them are paged without checking it again
+        let service_principals = service_principals(self).await?;
+        let params = params.unwrap_or_default();
+        let mut sink = MapSink::new(sink, |assignment| {
+            with_resolved_app_role(assignment, &service_principals)
+        });
+        // Only service principals exposing app roles can have them assigned
+        let mut resources: Vec<(&String, &Value)> = service_principals
+            .iter()
+            .filter(|(_, sp)| {
+                sp["appRoles"]
+                    .as_array()
+                    .is_some_and(|roles| !roles.is_empty())
+            })
+            .collect();
+        resources.sort_by_key(|(_, sp)| sp["displayName"].as_str().unwrap_or_default());
+        let mut count = 0;
+        for (id, resource) in resources {
+            let path = object_path("servicePrincipals", id, "appRoleAssignedTo")?;
+            // Keep auditing the other resources if one of them fails
+            match self
+                .stream_pages(&path, params.clone(), pages, &mut sink)
+                .await
+            {
+                Ok(fetched) => count += fetched,
+                Err(e) => eprintln!(
+                    "Failed to get app role assignments of {}: {}",
+                    resource["displayName"].as_str().unwrap_or(id),
+                    e
+                ),
+            }
+        }
+        Ok(count)
+    }
+}
+
+impl<C: ApiClient + ?Sized> PermissionsApi for C {}
+
+/// Get the service principals of the tenant with the permissions they
+/// expose, by ID
+async fn service_principals<C: ApiClient + ?Sized>(
+    client: &C,
+) -> Result<HashMap<String, Value>, RevelioError> {
+    let service_principals = client
+        .get_collection(
+            "/servicePrincipals",
+            vec![
+                ("$select", "id,appId,displayName,appRoles".to_string()),
+                ("$top", "999".to_string()),
+            ],
+            0,
+        )
+        .await?;
+    Ok(service_principals
+        .into_iter()
+        .filter_map(|sp| Some((sp["id"].as_str()?.to_string(), sp)))
+        .collect())
+}
+
+/// Add the names of the client and resource apps of a delegated permission
+/// grant, its scopes as a list and the dangerous ones among them
+/// (`dangerousPermissions`), flagging tenant-wide consents (`admin-consent`)
+/// and grants of dangerous permissions (`dangerous`)
+fn with_resolved_grant(mut grant: Value, service_principals: &HashMap<String, Value>) -> Value {
+    let client = grant["clientId"]
+        .as_str()
+        .and_then(|id| service_principals.get(id));
+    let resource = grant["resourceId"]
+        .as_str()
+        .and_then(|id| service_principals.get(id));
+    let scopes: Vec<&str> = grant["scope"]
+        .as_str()
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect();
+    let dangerous: Vec<Value> = scopes
+        .iter()
+        .filter(|scope| DANGEROUS_PERMISSIONS.contains(scope))
+        .map(|scope| Value::from(*scope))
+        .collect();
+    let mut flags: Vec<Value> = Vec::new();
+    if grant["consentType"] == "AllPrincipals" {
+        flags.push("admin-consent".into());
+    }
+    if !dangerous.is_empty() {
+        flags.push("dangerous".into());
+    }
+    let scopes: Vec<Value> = scopes.into_iter().map(Value::from).collect();
+    if let Some(obj) = grant.as_object_mut() {
+        let property = |sp: Option<&Value>, name: &str| sp.map_or(Value::Null, |sp| sp[name].clone());
+        obj.insert("clientName".to_string(), property(client, "displayName"));
+        obj.insert("clientAppId".to_string(), property(client, "appId"));
+        obj.insert("resourceName".to_string(), property(resource, "displayName"));
+        obj.insert("scopes".to_string(), Value::Array(scopes));
+        obj.insert("dangerousPermissions".to_string(), Value::Array(dangerous));
+        obj.insert("flags".to_string(), Value::Array(flags));
+    }
+    grant
+}
+
+/// Add the name (`permission`) and description of the app role of an
+/// assignment, flagging application permissions granted to apps
+/// (`application-permission`) and dangerous ones (`dangerous`)
+fn with_resolved_app_role(
+    mut assignment: Value,
+    service_principals: &HashMap<String, Value>,
+) -> Value {
+    let role_id = assignment["appRoleId"].as_str().unwrap_or_default();
+    let role = assignment["resourceId"]
+        .as_str()
+        .and_then(|id| service_principals.get(id))
+        .and_then(|resource| resource["appRoles"].as_array())
+        .and_then(|roles| roles.iter().find(|role| role["id"] == role_id));
+    let permission = match role {
+        Some(role) => role["value"].clone(),
+        None if role_id == DEFAULT_APP_ROLE_ID => Value::from("(default access)"),
+        None => Value::Null,
+    };
+    let mut flags: Vec<Value> = Vec::new();
+    if assignment["principalType"] == "ServicePrincipal" {
+        flags.push("application-permission".into());
+    }
+    if permission
+        .as_str()
+        .is_some_and(|permission| DANGEROUS_PERMISSIONS.contains(permission))
+    {
+        flags.push("dangerous".into());
+    }
+    if let Some(obj) = assignment.as_object_mut() {
+        obj.insert("permission".to_string(), permission);
+        obj.insert(
+            "permissionDisplayName".to_string(),
+            role.map_or(Value::Null, |role| role["displayName"].clone()),
+        );
+        obj.insert("flags".to_string(), Value::Array(flags));
+    }
+    assignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const GRAPH_ID: &str = "10000000-0000-0000-0000-000000000001";
+    const CLIENT_ID: &str = "10000000-0000-0000-0000-000000000002";
+    const READ_ALL_ROLE_ID: &str = "df021288-bdef-4463-88db-98f22de89214";
+    const WRITE_ALL_ROLE_ID: &str = "19dbc75e-c2e2-444c-a770-ec69d8559fc7";
+
+    fn service_principals() -> HashMap<String, Value> {
+        let graph = json!({
+            "id": GRAPH_ID,
+            "appId": "00000003-0000-0000-c000-000000000000",
+            "displayName": "Microsoft Graph",
+            "appRoles": [
+                {"id": READ_ALL_ROLE_ID, "value": "User.Read.All", "displayName": "Read all users' full profiles"},
+                {"id": WRITE_ALL_ROLE_ID, "value": "Directory.ReadWrite.All", "displayName": "Read and write directory data"},
+            ],
+        });
+        let client = json!({
+            "id": CLIENT_ID,
+            "appId": "20000000-0000-0000-0000-000000000002",
+            "displayName": "Contoso App",
+            "appRoles": [],
+        });
+        HashMap::from([
+            (GRAPH_ID.to_string(), graph),
+            (CLIENT_ID.to_string(), client),
+        ])
+    }
+
+    #[test]
+    fn resolved_grant_flags_consent_and_dangerous_scopes() {
+        let cases = [
+            ("Principal", "User.Read openid", json!([]), json!([])),
+            (
+                "AllPrincipals",
+                "User.Read",
+                json!(["admin-consent"]),
+                json!([]),
+            ),
+            (
+                "Principal",
+                "User.Read Directory.ReadWrite.All",
+                json!(["dangerous"]),
+                json!(["Directory.ReadWrite.All"]),
+            ),
+            (
+                "AllPrincipals",
+                " Mail.Read  Directory.AccessAsUser.All ",
+                json!(["admin-consent", "dangerous"]),
+                json!(["Directory.AccessAsUser.All"]),
+            ),
+        ];
+        for (consent_type, scope, flags, dangerous) in cases {
+            let grant = json!({
+                "clientId": CLIENT_ID,
+                "resourceId": GRAPH_ID,
+                "consentType": consent_type,
+                "scope": scope,
+            });
+            let grant = with_resolved_grant(grant, &service_principals());
+            assert_eq!(grant["flags"], flags, "{} {}", consent_type, scope);
+            assert_eq!(grant["dangerousPermissions"], dangerous, "{}", scope);
+        }
+    }
+
+    #[test]
+    fn resolved_grant_names_apps_and_splits_scopes() {
+        let grant = json!({
+            "clientId": CLIENT_ID,
+            "resourceId": GRAPH_ID,
+            "consentType": "Principal",
+            "scope": "openid User.Read",
+        });
+        let grant = with_resolved_grant(grant, &service_principals());
+        assert_eq!(grant["clientName"], "Contoso App");
+        assert_eq!(grant["clientAppId"], "20000000-0000-0000-0000-000000000002");
+        assert_eq!(grant["resourceName"], "Microsoft Graph");
+        assert_eq!(grant["scopes"], json!(["openid", "User.Read"]));
+
+        // Apps outside the known service principals are left unnamed
+        let grant = with_resolved_grant(json!({"clientId": "unknown"}), &service_principals());
+        assert_eq!(grant["clientName"], Value::Null);
+        assert_eq!(grant["scopes"], json!([]));
+        assert_eq!(grant["flags"], json!([]));
+    }
+
+    #[test]
+    fn resolved_app_role_flags_application_and_dangerous_permissions() {
+        let cases = [
+            ("User", READ_ALL_ROLE_ID, json!("User.Read.All"), json!([])),
+            (
+                "ServicePrincipal",
+                READ_ALL_ROLE_ID,
+                json!("User.Read.All"),
+                json!(["application-permission"]),
+            ),
+            (
+                "ServicePrincipal",
+                WRITE_ALL_ROLE_ID,
+                json!("Directory.ReadWrite.All"),
+                json!(["application-permission", "dangerous"]),
+            ),
+            (
+                "Group",
+                WRITE_ALL_ROLE_ID,
+                json!("Directory.ReadWrite.All"),
+                json!(["dangerous"]),
+            ),
+            (
+                "User",
+                DEFAULT_APP_ROLE_ID,
+                json!("(default access)"),
+                json!([]),
+            ),
+            (
+                "ServicePrincipal",
+                "unknown",
+                Value::Null,
+                json!(["application-permission"]),
+            ),
+        ];
+        for (principal_type, role_id, permission, flags) in cases {
+            let assignment = json!({
+                "principalType": principal_type,
+                "resourceId": GRAPH_ID,
+                "appRoleId": role_id,
+            });
+            let assignment = with_resolved_app_role(assignment, &service_principals());
+            assert_eq!(
+                assignment["permission"], permission,
+                "{} {}",
+                principal_type, role_id
+            );
+            assert_eq!(assignment["flags"], flags, "{} {}", principal_type, role_id);
+        }
+    }
+
+    #[test]
+    fn resolved_app_role_describes_permission() {
+        let assignment = json!({
+            "principalType": "ServicePrincipal",
+            "resourceId": GRAPH_ID,
+            "appRoleId": WRITE_ALL_ROLE_ID,
+        });
+        let assignment = with_resolved_app_role(assignment, &service_principals());
+        assert_eq!(
+            assignment["permissionDisplayName"],
+            "Read and write directory data"
+        );
+
+        // Roles of unknown resources cannot be resolved
+        let assignment = json!({"resourceId": "unknown", "appRoleId": WRITE_ALL_ROLE_ID});
+        let assignment = with_resolved_app_role(assignment, &service_principals());
+        assert_eq!(assignment["permission"], Value::Null);
+        assert_eq!(assignment["permissionDisplayName"], Value::Null);
+    }
+}
@@ -0,0 +1,271 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::{
+    object_name, with_default_expand, with_default_select, with_object_type, ApiClient,
+    RelationshipsSink,
+};
+use crate::core::constants::PRIVILEGED_ROLE_TEMPLATES;
+use crate::core::output::{CollectionSink, MapSink};
+use crate::error::RevelioError;
+
+/// Properties selected for activated directory roles when no `$select` is given
+pub const DEFAULT_DIRECTORY_ROLE_SELECT: &str = "id,displayName,description,roleTemplateId";
+
+/// Properties selected for role definitions when no `$select` is given
+pub const DEFAULT_ROLE_DEFINITION_SELECT: &str =
+    "id,displayName,description,templateId,isBuiltIn,isEnabled,rolePermissions";
+
+#[async_trait(?Send)]
+pub trait RolesApi: ApiClient {
+    /// Get the activated directory roles with their members
+    async fn get_directory_roles(
+        &self,
+        params: Option<Vec<(&str, String)>>,
+        pages: u16,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize, RevelioError> {
+        let params = with_default_select(params.unwrap_or_default(), DEFAULT_DIRECTORY_ROLE_SELECT);
+        let mut sink = MapSink::new(sink, with_role_members);
+        let mut sink =
+            RelationshipsSink::new(self, "directoryRoles", &["members"], None, &mut sink);
+        self.stream_collection("/directoryRoles", params, pages, &mut sink)
+            .await
+    }
+    /// Get the built-in and custom directory role definitions
+    async fn get_role_definitions(
+        &self,
+        params: Option<Vec<(&str, String)>>,
+        pages: u16,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize, RevelioError> {
+        let params =
+            with_default_select(params.unwrap_or_default(), DEFAULT_ROLE_DEFINITION_SELECT);
+        let mut sink = MapSink::new(sink, |mut definition: Value| {
+            let privileged = is_privileged(&definition["templateId"]);
+            if let Some(obj) = definition.as_object_mut() {
+                obj.insert("privileged".to_string(), Value::Bool(privileged));
+            }
+            definition
+        });
+        self.stream_collection(
+            "/roleManagement/directory/roleDefinitions",
+            params,
+            pages,
+            &mut sink,
+        )
+        .await
+    }
+    /// Get the active directory role assignments, resolving roles and principals
+    async fn get_role_assignments(
+        &self,
+        params: Option<Vec<(&str, String)>>,
+        pages: u16,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize, RevelioError> {
+        stream_role_grants(
+            self,
+            "/roleManagement/directory/roleAssignments",
+            params,
+            pages,
+            sink,
+        )
+        .await
+    }
+    /// Get the directory roles principals are eligible to activate through
+    /// Privileged Identity Management, resolving roles and principals
+    async fn get_role_eligibility_schedules(
+        &self,
+        params: Option<Vec<(&str, String)>>,
+        pages: u16,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize, RevelioError> {
+        stream_role_grants(
+            self,
+            "/roleManagement/directory/roleEligibilitySchedules",
+            params,
+            pages,
+            sink,
+        )
+        .await
+    }
+}
+
+impl<C: ApiClient + ?Sized> RolesApi for C {}
+
+/// Stream role assignments or eligibilities, expanding their principal and
+/// adding the name of their role
+async fn stream_role_grants<C: ApiClient + ?Sized>(
+    client: &C,
+    path: &str,
+    params: Option<Vec<(&str, String)>>,
+    pages: u16,
+    sink: &mut dyn CollectionSink,
+) -> Result<usize, RevelioError> {
+    let role_names = match role_names(client).await {
+        Ok(role_names) => role_names,
+        Err(e) => {
+            // Privileged roles can still be named from their template ID
+            eprintln!("Failed to resolve role names: {}", e);
+            HashMap::new()
+        }
+    };
+    let params = with_default_expand(params.unwrap_or_default(), "principal");
+    let mut sink = MapSink::new(sink, |grant| with_resolved_grant(grant, &role_names));
+    client.stream_collection(path, params, pages, &mut sink).await
+}
+
+/// Get the names of the role definitions, by ID
+pub(crate) async fn role_names<C: ApiClient + ?Sized>(
+    client: &C,
+) -> Result<HashMap<String, String>, RevelioError> {
+    let definitions = client
+        .get_collection(
+            "/roleManagement/directory/roleDefinitions",
+            vec![("$select", "id,displayName".to_string())],
+            0,
+        )
+        .await?;
+    Ok(definitions
+        .iter()
+        .filter_map(|definition| {
+            Some((
+                definition["id"].as_str()?.to_string(),
+                definition["displayName"].as_str()?.to_string(),
+            ))
+        })
+        .collect())
+}
+
+/// Check whether a role template ID belongs to a privileged role
+fn is_privileged(template_id: &Value) -> bool {
+    template_id
+        .as_str()
+        .is_some_and(|id| PRIVILEGED_ROLE_TEMPLATES.contains_key(id))
+}
+
+/// Add the type of the members of a directory role, their names
+/// (`memberNames`) and whether the role is privileged
+fn with_role_members(mut role: Value) -> Value {
+    let privileged = is_privileged(&role["roleTemplateId"]);
+    let members: Vec<Value> = role["members"]
+        .as_array()
+        .map(|members| members.iter().cloned().map(with_object_type).collect())
+        .unwrap_or_default();
+    let member_names: Vec<Value> = members
+        .iter()
+        .filter_map(|member| object_name(member).map(Value::from))
+        .collect();
+    if let Some(obj) = role.as_object_mut() {
+        obj.insert("privileged".to_string(), Value::Bool(privileged));
+        obj.insert("members".to_string(), Value::Array(members));
+        obj.insert("memberNames".to_string(), Value::Array(member_names));
+    }
+    role
+}
+
+/// Add the role name (`roleName`), whether the role is privileged and the
+/// type and name of the expanded principal of a role assignment or
+/// eligibility
+fn with_resolved_grant(mut grant: Value, role_names: &HashMap<String, String>) -> Value {
+    let role_id = grant["roleDefinitionId"].as_str().unwrap_or_default();
+    let role_name = role_names
+        .get(role_id)
+        .map(|name| name.as_str())
+        .or(PRIVILEGED_ROLE_TEMPLATES.get(role_id).copied())
+        .map(Value::from)
+        .unwrap_or(Value::Null);
+    let privileged = PRIVILEGED_ROLE_TEMPLATES.contains_key(role_id);
+    let principal = with_object_type(grant["principal"].clone());
+    let principal_type = principal["objectType"].clone();
+    let principal_name = object_name(&principal)
+        .map(Value::from)
+        .unwrap_or(Value::Null);
+    if let Some(obj) = grant.as_object_mut() {
+        obj.insert("roleName".to_string(), role_name);
+        obj.insert("privileged".to_string(), Value::Bool(privileged));
+        obj.insert("principalType".to_string(), principal_type);
+        obj.insert("principalName".to_string(), principal_name);
+    }
+    grant
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const GLOBAL_ADMINISTRATOR: &str = "62e90394-69f5-4237-9190-012177145e10";
+    const CUSTOM_ROLE: &str = "30000000-0000-0000-0000-000000000001";
+
+    fn role_names() -> HashMap<String, String> {
+        HashMap::from([
+            (
+                GLOBAL_ADMINISTRATOR.to_string(),
+                "Global Administrator".to_string(),
+            ),
+            (CUSTOM_ROLE.to_string(), "Helpdesk Operator".to_string()),
+        ])
+    }
+
+    #[test]
+    fn privileged_role_templates() {
+        assert!(is_privileged(&json!(GLOBAL_ADMINISTRATOR)));
+        assert!(!is_privileged(&json!(CUSTOM_ROLE)));
+        assert!(!is_privileged(&Value::Null));
+    }
+
+    #[test]
+    fn resolved_grant_names_role() {
+        let cases = [
+            // Name from the role definitions
+            (
+                json!(GLOBAL_ADMINISTRATOR),
+                role_names(),
+                json!("Global Administrator"),
+                true,
+            ),
+            (
+                json!(CUSTOM_ROLE),
+                role_names(),
+                json!("Helpdesk Operator"),
+                false,
+            ),
+            // Name of the privileged role template when definitions are unknown
+            (
+                json!(GLOBAL_ADMINISTRATOR),
+                HashMap::new(),
+                json!("Global Administrator"),
+                true,
+            ),
+            (json!(CUSTOM_ROLE), HashMap::new(), Value::Null, false),
+            // Grant without a role definition
+            (Value::Null, role_names(), Value::Null, false),
+        ];
+        for (role_id, names, role_name, privileged) in cases {
+            let grant = with_resolved_grant(json!({"roleDefinitionId": role_id}), &names);
+            assert_eq!(grant["roleName"], role_name, "{}", role_id);
+            assert_eq!(grant["privileged"], privileged, "{}", role_id);
+        }
+    }
+
+    #[test]
+    fn resolved_grant_names_principal() {
+        let grant = json!({
+            "roleDefinitionId": GLOBAL_ADMINISTRATOR,
+            "principal": {
+                "@odata.type": "#microsoft.graph.user",
+                "displayName": "Adele Vance",
+                "userPrincipalName": "adele@contoso.com",
+            },
+        });
+        let grant = with_resolved_grant(grant, &role_names());
+        assert_eq!(grant["principalType"], "user");
+        assert_eq!(grant["principalName"], "adele@contoso.com");
+
+        let grant = with_resolved_grant(json!({"roleDefinitionId": CUSTOM_ROLE}), &role_names());
+        assert_eq!(grant["principalType"], Value::Null);
+        assert_eq!(grant["principalName"], Value::Null);
+    }
+}
@@ -0,0 +1,39 @@
+use async_trait::async_trait;
+
+use super::applications::with_credentials_summary;
+use super::{with_default_select, ApiClient, RelationshipsSink, RELATED_OBJECT_SELECT};
+use crate::core::output::{CollectionSink, MapSink};
+use crate::error::RevelioError;
+
+/// Properties selected for service principals when no `$select` is given
+pub const DEFAULT_SERVICE_PRINCIPAL_SELECT: &str = "id,appId,displayName,\
+    servicePrincipalType,accountEnabled,appOwnerOrganizationId,appRoleAssignmentRequired,\
+    servicePrincipalNames,replyUrls,passwordCredentials,keyCredentials,appRoles,tags";
+
+#[async_trait(?Send)]
+pub trait ServicePrincipalsApi: ApiClient {
+    /// Get the service principals (enterprise apps) of the tenant with their
+    /// credentials metadata and owners, writing them to `sink` page by page
+    /// and returning the number of service principals fetched
+    async fn get_service_principals(
+        &self,
+        params: Option<Vec<(&str, String)>>,
+        pages: u16,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize, RevelioError> {
+        let params =
+            with_default_select(params.unwrap_or_default(), DEFAULT_SERVICE_PRINCIPAL_SELECT);
+        let mut sink = MapSink::new(sink, with_credentials_summary);
+        let mut sink = RelationshipsSink::new(
+            self,
+            "servicePrincipals",
+            &["owners"],
+            Some(RELATED_OBJECT_SELECT),
+            &mut sink,
+        );
+        self.stream_collection("/servicePrincipals", params, pages, &mut sink)
+            .await
+    }
+}
+
+impl<C: ApiClient + ?Sized> ServicePrincipalsApi for C {}
@@ -0,0 +1,150 @@
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use super::ApiClient;
+use crate::core::output::{CollectionSink, MapSink};
+use crate::error::RevelioError;
+
+#[async_trait(?Send)]
+pub trait TenantApi: ApiClient {
+    /// Get the organization (tenant) details
+    async fn get_organization(
+        &self,
+        params: Option<Vec<(&str, String)>>,
+    ) -> Result<Value, RevelioError> {
+        self.ensure_valid_token().await?;
+        let mut organization = self
+            .get_json("/organization", params.unwrap_or_default())
+            .await?["value"][0]
+            .take();
+        if organization.is_null() {
+            return Err(RevelioError::Parse("no organization returned".to_string()));
+        }
+        if let Some(obj) = organization.as_object_mut() {
+            obj.remove("@odata.type");
+        }
+        Ok(organization)
+    }
+    /// Get the domains of the tenant, writing them to `sink` page by page and
+    /// returning the number of domains fetched
+    async fn get_domains(
+        &self,
+        params: Option<Vec<(&str, String)>>,
+        pages: u16,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize, RevelioError> {
+        let mut sink = MapSink::new(sink, with_domain_flags);
+        self.stream_collection("/domains", params.unwrap_or_default(), pages, &mut sink)
+            .await
+    }
+    /// Get the licenses (SKUs) the tenant subscribed to
+    async fn get_subscribed_skus(&self) -> Result<Vec<Value>, RevelioError> {
+        self.get_collection("/subscribedSkus", Vec::new(), 0).await
+    }
+    /// Get a summary of the tenant: organization details (queried with
+    /// `params`), domains and licenses
+    async fn get_tenant_summary(
+        &self,
+        params: Option<Vec<(&str, String)>>,
+    ) -> Result<Value, RevelioError> {
+        let organization = self.get_organization(params).await?;
+        // Domains and licenses need other permissions, so they are optional
+        let domains = match self.get_collection("/domains", Vec::new(), 0).await {
+            Ok(domains) => Some(
+                domains
+                    .into_iter()
+                    .map(with_domain_flags)
+                    .collect::<Vec<_>>(),
+            ),
+            Err(e) => {
+                eprintln!("Failed to get domains: {}", e);
+                None
+            }
+        };
+        let skus = match self.get_subscribed_skus().await {
+            Ok(skus) => Some(skus),
+            Err(e) => {
+                eprintln!("Failed to get subscribed SKUs: {}", e);
+                None
+            }
+        };
+
+        let domain_names = |authentication_type: &str| -> Option<Vec<Value>> {
+            domains.as_ref().map(|domains| {
+                domains
+                    .iter()
+                    .filter(|domain| domain["authenticationType"] == authentication_type)
+                    .map(|domain| domain["id"].clone())
+                    .collect()
+            })
+        };
+        let enabled_plans: Vec<Value> = organization["assignedPlans"]
+            .as_array()
+            .map(|plans| {
+                let mut services: Vec<Value> = plans
+                    .iter()
+                    .filter(|plan| plan["capabilityStatus"] == "Enabled")
+                    .map(|plan| plan["service"].clone())
+                    .collect();
+                services.sort_by_key(|service| service.to_string());
+                services.dedup();
+                services
+            })
+            .unwrap_or_default();
+        let licenses: Option<Vec<Value>> = skus.as_ref().map(|skus| {
+            skus.iter()
+                .map(|sku| {
+                    json!({
+                        "skuPartNumber": sku["skuPartNumber"],
+                        "skuId": sku["skuId"],
+                        "capabilityStatus": sku["capabilityStatus"],
+                        "enabledUnits": sku["prepaidUnits"]["enabled"],
+                        "consumedUnits": sku["consumedUnits"],
+                    })
+                })
+                .collect()
+        });
+
+        Ok(json!({
+            "tenantId": organization["id"],
+            "displayName": organization["displayName"],
+            "createdDateTime": organization["createdDateTime"],
+            "countryLetterCode": organization["countryLetterCode"],
+            "verifiedDomains": organization["verifiedDomains"],
+            "onPremisesSyncEnabled": organization["onPremisesSyncEnabled"],
+            "onPremisesLastSyncDateTime": organization["onPremisesLastSyncDateTime"],
+            "technicalNotificationMails": organization["technicalNotificationMails"],
+            "enabledServices": enabled_plans,
+            "federatedDomains": domain_names("Federated"),
+            "managedDomains": domain_names("Managed"),
+            "licenses": licenses,
+            "organization": organization,
+            "domains": domains,
+            "subscribedSkus": skus,
+        }))
+    }
+}
+
+impl<C: ApiClient + ?Sized> TenantApi for C {}
+
+/// Add `flags` listing whether a domain is `federated`, `unverified`, the
+/// `default` one or the `initial` (onmicrosoft.com) one
+fn with_domain_flags(mut domain: Value) -> Value {
+    let mut flags: Vec<Value> = Vec::new();
+    if domain["authenticationType"] == "Federated" {
+        flags.push("federated".into());
+    }
+    if domain["isVerified"] == false {
+        flags.push("unverified".into());
+    }
+    if domain["isDefault"] == true {
+        flags.push("default".into());
+    }
+    if domain["isInitial"] == true {
+        flags.push("initial".into());
+    }
+    if let Some(obj) = domain.as_object_mut() {
+        obj.insert("flags".to_string(), Value::Array(flags));
+    }
+    domain
+}
@@ -1,16 +1,331 @@
 use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
 use serde_json::Value;
+use std::collections::HashMap;
+
+use super::{object_path, with_default_select, with_object_type, ApiClient};
+use crate::core::output::CollectionSink;
+use crate::error::RevelioError;
+
+/// Properties selected for users when no `$select` is given
+pub const DEFAULT_USER_SELECT: &str = "id,businessPhones,displayName,givenName,\
+    jobTitle,mail,mobilePhone,officeLocation,surname,userPrincipalName,\
+    onPremisesDistinguishedName,onPremisesDomainName,onPremisesLastSyncDateTime,\
+    onPremisesSecurityIdentifier,onPremisesSamAccountName,onPremisesSyncEnabled,\
+    onPremisesUserPrincipalName,passwordPolicies";
+
+/// Properties selected for users when getting their authentication methods
+/// and no `$select` is given
+pub const DEFAULT_AUTHENTICATION_USER_SELECT: &str =
+    "id,displayName,userPrincipalName,accountEnabled,userType";
+
+// Authentication methods that do not satisfy MFA on their own
+const SINGLE_FACTOR_METHODS: [&str; 2] = ["password", "email"];
+
 #[async_trait(?Send)]
-pub trait UsersApi {
+pub trait UsersApi: ApiClient {
     /// Get the number of users in the tenant
     async fn get_users_count(
         &self,
         params: Option<Vec<(&str, String)>>,
-    ) -> Result<usize, Box<dyn std::error::Error>>;
-    /// Get the list of users in the tenant
+    ) -> Result<usize, RevelioError> {
+        self.ensure_valid_token().await?;
+        let params = params.unwrap_or_default();
+        let url = format!("{}/users/$count", self.get_base_path());
+        let response = self
+            .send_request(self.get_client().get(url.as_str()).query(&params))
+            .await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let text = response.text().await?;
+        if !status.is_success() {
+            let body = serde_json::from_str(&text).unwrap_or_default();
+            return Err(RevelioError::from_response(status, &headers, &body));
+        }
+        text.trim()
+            .parse::<usize>()
+            .map_err(|_| RevelioError::Parse(format!("invalid users count `{}`", text)))
+    }
+    /// Get the list of users in the tenant, writing them to `sink` page by
+    /// page and returning the number of users fetched
     async fn get_users(
         &self,
         params: Option<Vec<(&str, String)>>,
         pages: u16,
-    ) -> Result<Value, Box<dyn std::error::Error>>;
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize, RevelioError> {
+        let params = with_default_select(params.unwrap_or_default(), DEFAULT_USER_SELECT);
+        self.stream_collection("/users", params, pages, sink).await
+    }
+    /// Get the list of users with their registered authentication methods
+    /// and MFA registration details, requesting the methods of up to
+    /// `concurrency` users at the same time
+    async fn get_authentication_methods(
+        &self,
+        params: Option<Vec<(&str, String)>>,
+        pages: u16,
+        concurrency: usize,
+        sink: &mut dyn CollectionSink,
+    ) -> Result<usize, RevelioError> {
+        let registrations = match registration_details(self).await {
+            Ok(registrations) => registrations,
+            Err(e) => {
+                // Methods alone still tell which users lack MFA
+                eprintln!("Failed to get user registration details: {}", e);
+                HashMap::new()
+            }
+        };
+        let params = with_default_select(
+            params.unwrap_or_default(),
+            DEFAULT_AUTHENTICATION_USER_SELECT,
+        );
+        let mut sink = AuthenticationMethodsSink {
+            client: self,
+            registrations: &registrations,
+            concurrency: concurrency.max(1),
+            inner: sink,
+        };
+        self.stream_collection("/users", params, pages, &mut sink)
+            .await
+    }
+}
+
+impl<C: ApiClient + ?Sized> UsersApi for C {}
+
+/// Get the authentication methods registration report, by user ID
+async fn registration_details<C: ApiClient + ?Sized>(
+    client: &C,
+) -> Result<HashMap<String, Value>, RevelioError> {
+    let details = client
+        .get_collection(
+            "/reports/authenticationMethods/userRegistrationDetails",
+            Vec::new(),
+            0,
+        )
+        .await?;
+    Ok(details
+        .into_iter()
+        .filter_map(|detail| Some((detail["id"].as_str()?.to_string(), detail)))
+        .collect())
+}
+
+/// Sink adding the authentication methods of every user before passing them on
+struct AuthenticationMethodsSink<'a, C: ?Sized> {
+    client: &'a C,
+    registrations: &'a HashMap<String, Value>,
+    concurrency: usize,
+    inner: &'a mut dyn CollectionSink,
+}
+
+#[async_trait(?Send)]
+impl<C: ApiClient + ?Sized> CollectionSink for AuthenticationMethodsSink<'_, C> {
+    async fn write_page(&mut self, items: &[Value], next_link: Option<&str>) -> Result<(), RevelioError> {
+        let client = self.client;
+        let registrations = self.registrations;
+        let users: Vec<Value> = stream::iter(items.iter().cloned())
+            .map(|user| with_authentication_methods(client, registrations, user))
+            .buffered(self.concurrency)
+            .collect()
+            .await;
+        self.inner.write_page(&users, next_link).await
+    }
+
+    fn resume_link(&mut self) -> Option<String> {
+        self.inner.resume_link()
+    }
+}
+
+/// Get the authentication methods of a user and add them along with its
+/// registration details
+async fn with_authentication_methods<C: ApiClient + ?Sized>(
+    client: &C,
+    registrations: &HashMap<String, Value>,
+    user: Value,
+) -> Value {
+    let methods = match authentication_methods(client, &user).await {
+        Ok(methods) => methods,
+        Err(e) => {
+            eprintln!(
+                "Failed to get authentication methods of {}: {}",
+                user["userPrincipalName"].as_str().unwrap_or_default(),
+                e
+            );
+            Vec::new()
+        }
+    };
+    let registration = user["id"]
+        .as_str()
+        .and_then(|id| registrations.get(id))
+        .cloned()
+        .unwrap_or(Value::Null);
+    with_method_flags(user, methods, registration)
+}
+
+/// Add the authentication methods of a user, their types (`methodTypes`),
+/// its registration details and a `flags` property: `no-mfa` (no method
+/// besides password and email), `sms` (phone number registered),
+/// `authenticator` and `fido2`
+fn with_method_flags(mut user: Value, methods: Vec<Value>, registration: Value) -> Value {
+    let mut method_types: Vec<String> = methods
+        .iter()
+        .filter_map(|method| method["objectType"].as_str())
+        .map(|method_type| method_type.trim_end_matches("AuthenticationMethod").to_string())
+        .collect();
+    method_types.sort();
+    method_types.dedup();
+
+    let mut flags: Vec<Value> = Vec::new();
+    // Without any method, e.g. for lack of permissions, the report decides
+    let mfa = if methods.is_empty() {
+        registration["isMfaRegistered"].as_bool()
+    } else {
+        Some(
+            method_types
+                .iter()
+                .any(|method_type| !SINGLE_FACTOR_METHODS.contains(&method_type.as_str())),
+        )
+    };
+    if mfa == Some(false) {
+        flags.push("no-mfa".into());
+    }
+    let registered = |method_type: &str, registered_method: &str| {
+        method_types.iter().any(|t| t == method_type)
+            || registration["methodsRegistered"]
+                .as_array()
+                .is_some_and(|methods| methods.iter().any(|m| m == registered_method))
+    };
+    if registered("phone", "mobilePhone") {
+        flags.push("sms".into());
+    }
+    if registered("microsoftAuthenticator", "microsoftAuthenticatorPush") {
+        flags.push("authenticator".into());
+    }
+    if registered("fido2", "fido2SecurityKey") {
+        flags.push("fido2".into());
+    }
+    if let Some(obj) = user.as_object_mut() {
+        obj.insert("authenticationMethods".to_string(), Value::Array(methods));
+        obj.insert(
+            "methodTypes".to_string(),
+            Value::Array(method_types.into_iter().map(Value::from).collect()),
+        );
+        obj.insert("registrationDetails".to_string(), registration);
+        obj.insert("flags".to_string(), Value::Array(flags));
+    }
+    user
+}
+
+async fn authentication_methods<C: ApiClient + ?Sized>(
+    client: &C,
+    user: &Value,
+) -> Result<Vec<Value>, RevelioError> {
+    let id = user["id"]
+        .as_str()
+        .ok_or_else(|| RevelioError::Parse("user without ID".to_string()))?;
+    let path = object_path("users", id, "authentication/methods")?;
+    let methods = client.get_json(&path, Vec::new()).await?;
+    Ok(methods["value"]
+        .as_array()
+        .map(|methods| methods.iter().cloned().map(with_object_type).collect())
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Authentication methods as returned by Graph, with their object type
+    fn methods(types: &[&str]) -> Vec<Value> {
+        types
+            .iter()
+            .map(|method_type| {
+                with_object_type(json!({
+                    "@odata.type": format!("#microsoft.graph.{}AuthenticationMethod", method_type),
+                    "id": method_type,
+                }))
+            })
+            .collect()
+    }
+
+    fn flags(methods: Vec<Value>, registration: Value) -> Vec<Value> {
+        let user = json!({"id": "1", "userPrincipalName": "user@contoso.com"});
+        with_method_flags(user, methods, registration)["flags"]
+            .as_array()
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn method_flags_from_methods() {
+        let cases: [(&[&str], &[&str]); 6] = [
+            (&["password"], &["no-mfa"]),
+            (&["password", "email"], &["no-mfa"]),
+            (&["password", "phone"], &["sms"]),
+            (&["password", "microsoftAuthenticator"], &["authenticator"]),
+            (&["password", "fido2"], &["fido2"]),
+            (
+                &["password", "phone", "microsoftAuthenticator", "fido2"],
+                &["sms", "authenticator", "fido2"],
+            ),
+        ];
+        for (method_types, expected) in cases {
+            assert_eq!(
+                flags(methods(method_types), Value::Null),
+                expected.iter().map(|flag| json!(flag)).collect::<Vec<_>>(),
+                "methods {:?}",
+                method_types
+            );
+        }
+    }
+
+    #[test]
+    fn method_flags_from_registration_details() {
+        // Without methods, e.g. when they could not be read, the report decides
+        let cases: [(Value, &[&str]); 5] = [
+            (Value::Null, &[]),
+            (
+                json!({"isMfaRegistered": false, "methodsRegistered": []}),
+                &["no-mfa"],
+            ),
+            (
+                json!({"isMfaRegistered": true, "methodsRegistered": ["mobilePhone"]}),
+                &["sms"],
+            ),
+            (
+                json!({"isMfaRegistered": true, "methodsRegistered": ["microsoftAuthenticatorPush"]}),
+                &["authenticator"],
+            ),
+            (
+                json!({"isMfaRegistered": true, "methodsRegistered": ["fido2SecurityKey"]}),
+                &["fido2"],
+            ),
+        ];
+        for (registration, expected) in cases {
+            assert_eq!(
+                flags(Vec::new(), registration.clone()),
+                expected.iter().map(|flag| json!(flag)).collect::<Vec<_>>(),
+                "registration {}",
+                registration
+            );
+        }
+        // Methods that could be read take precedence over the report
+        assert_eq!(
+            flags(methods(&["password"]), json!({"isMfaRegistered": true})),
+            vec![json!("no-mfa")]
+        );
+    }
+
+    #[test]
+    fn method_flags_add_methods_and_registration() {
+        let registration = json!({"id": "1", "isMfaRegistered": true});
+        let user = with_method_flags(
+            json!({"id": "1"}),
+            methods(&["phone", "password", "phone"]),
+            registration.clone(),
+        );
+        assert_eq!(user["methodTypes"], json!(["password", "phone"]));
+        assert_eq!(user["authenticationMethods"].as_array().unwrap().len(), 3);
+        assert_eq!(user["registrationDetails"], registration);
+    }
 }
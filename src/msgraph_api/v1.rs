@@ -1,3 +1 @@
 pub mod client;
-pub mod me;
-pub mod users;
@@ -1,67 +1,34 @@
-use crate::{helpers::QueryConfig, msgraph_api::ApiClient};
+use crate::{
+    core::auth::AuthResult,
+    error::Result,
+    msgraph_api::{
+        client::{BaseClient, RetryPolicy},
+        ApiClient,
+    },
+};
 
 pub struct ApiClientV1 {
-    client: reqwest::Client,
-    base_path: String,
-    token: String,
+    base: BaseClient,
 }
 
 impl ApiClientV1 {
-    pub fn new(token: String, proxy: Option<String>, nossl: bool) -> Self {
-        let client = match proxy {
-            Some(proxy) => reqwest::Client::builder()
-                .proxy(reqwest::Proxy::all(proxy).unwrap())
-                .danger_accept_invalid_certs(nossl)
-                .build()
-                .expect("Failed to create reqwest client"),
-            None => reqwest::Client::builder()
-                .danger_accept_invalid_certs(nossl)
-                .build()
-                .expect("Failed to create reqwest client"),
-        };
-        let base_path = "https://graph.microsoft.com/v1.0".to_owned();
-        ApiClientV1 {
-            client,
-            base_path,
-            token,
-        }
-    }
-
-    pub async fn check_access_token_validity(&self) -> Result<bool, reqwest::Error> {
-        let response = self
-            .client
-            .get(format!("{}/me", self.base_path))
-            .header("Authorization", format!("Bearer {}", self.token))
-            .send()
-            .await?;
-
-        if response.status().is_success() {
-            Ok(true) // Access token is valid
-        } else {
-            Ok(false) // Access token is not valid
-        }
+    pub fn new(
+        token: String,
+        auth_result: Option<AuthResult>,
+        graph_host: String,
+        proxy: Option<String>,
+        nossl: bool,
+        retry_policy: RetryPolicy,
+    ) -> Result<Self> {
+        let base_path = format!("{}/v1.0", graph_host);
+        Ok(ApiClientV1 {
+            base: BaseClient::new(token, auth_result, base_path, proxy, nossl, retry_policy)?,
+        })
     }
 }
 
 impl ApiClient for ApiClientV1 {
-    fn get_token(&self) -> &str {
-        &self.token
-    }
-    fn get_client(&self) -> &reqwest::Client {
-        &self.client
-    }
-    fn get_base_path(&self) -> &str {
-        &self.base_path
-    }
-    fn query_config_to_params(&self, query_config: &QueryConfig) -> Vec<(&str, String)> {
-        let mut query_vec: Vec<(&str, String)> = Vec::new();
-        if let Some(select) = &query_config.select {
-            query_vec.push(("$select", select.clone()));
-        }
-        if let Some(skiptoken) = &query_config.skiptoken {
-            query_vec.push(("$skiptoken", skiptoken.clone()));
-        }
-        query_vec.push(("$top", query_config.top.to_string()));
-        query_vec
+    fn get_base_client(&self) -> &BaseClient {
+        &self.base
     }
 }